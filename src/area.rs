@@ -0,0 +1,100 @@
+//! Hex shapes covered by area effects such as spells and breath weapons
+
+use serde::{Deserialize, Serialize};
+use crate::{Direction, HexPosition};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AreaShape {
+    /// Every hex within `radius` of the origin, the origin included
+    Burst { radius: i32 },
+    /// A 60 degree wedge spreading `length` hexes out from the origin
+    Cone { direction: Direction, length: i32 },
+    /// A straight line `length` hexes long starting next to the origin
+    Line { direction: Direction, length: i32 },
+    /// Only the hexes exactly `radius` away from the origin
+    Ring { radius: i32 },
+}
+
+impl AreaShape {
+    /// Hexes covered with the shape placed at `origin`, on the origin's level
+    ///
+    /// Cones and lines leave out the origin itself, which is where the
+    /// caster or breathing creature usually stands.
+    pub fn hexes(&self, origin: HexPosition) -> Vec<HexPosition> {
+        match *self {
+            AreaShape::Burst { radius } => within(origin, radius).collect(),
+            AreaShape::Ring { radius } => within(origin, radius)
+                .filter(|hex| origin.planar_distance(hex) == radius)
+                .collect(),
+            AreaShape::Line { direction, length } => {
+                let mut hex = origin;
+                (0..length.max(0))
+                    .map(|_| {
+                        hex = hex.neighbor(direction);
+                        hex
+                    })
+                    .collect()
+            }
+            AreaShape::Cone { direction, length } => {
+                let axis = pixel(direction.offset());
+                within(origin, length)
+                    .filter(|hex| *hex != origin)
+                    .filter(|hex| {
+                        let offset = pixel((hex.q - origin.q, hex.r - origin.r));
+                        let cos = (axis.0 * offset.0 + axis.1 * offset.1)
+                            / ((axis.0.hypot(axis.1)) * offset.0.hypot(offset.1));
+                        // Within 30 degrees either side of the cone's axis
+                        cos >= (30f64.to_radians()).cos() - 1e-9
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Whether `hex` lies within the shape placed at `origin`
+    pub fn contains(&self, origin: HexPosition, hex: &HexPosition) -> bool {
+        self.hexes(origin).iter().any(|h| h.q == hex.q && h.r == hex.r)
+    }
+}
+
+/// Hexes within `radius` of `center`, in a fixed order
+fn within(center: HexPosition, radius: i32) -> impl Iterator<Item = HexPosition> {
+    let radius = radius.max(0);
+    (-radius..=radius).flat_map(move |dq| {
+        let low = (-radius).max(-dq - radius);
+        let high = radius.min(-dq + radius);
+        (low..=high).map(move |dr| HexPosition::new(center.q + dq, center.r + dr, center.z))
+    })
+}
+
+/// Planar position of an axial offset on flat-topped hexes (north is -r)
+fn pixel((q, r): (i32, i32)) -> (f64, f64) {
+    (1.5 * q as f64, 3f64.sqrt() * (r as f64 + q as f64 / 2.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shape_sizes() {
+        let origin = HexPosition::new_2d(5, 5);
+        assert_eq!(AreaShape::Burst { radius: 2 }.hexes(origin).len(), 19);
+        assert_eq!(AreaShape::Ring { radius: 2 }.hexes(origin).len(), 12);
+
+        let line = AreaShape::Line { direction: Direction::South, length: 3 }.hexes(origin);
+        assert_eq!(line, vec![
+            HexPosition::new_2d(5, 6),
+            HexPosition::new_2d(5, 7),
+            HexPosition::new_2d(5, 8),
+        ]);
+
+        // One hex wide at the mouth, spreading out to five
+        let cone = AreaShape::Cone { direction: Direction::North, length: 4 };
+        let hexes = cone.hexes(origin);
+        let width = |d: i32| hexes.iter().filter(|h| origin.planar_distance(h) == d).count();
+        assert_eq!((width(1), width(2), width(3), width(4)), (1, 3, 3, 5));
+        assert!(cone.contains(origin, &HexPosition::new_2d(5, 1)));
+        assert!(!cone.contains(origin, &HexPosition::new_2d(5, 6)));
+    }
+}
@@ -0,0 +1,63 @@
+use clap::Parser;
+use std::fs;
+use std::path::Path;
+
+use harmony::simulation::{Party, SimulationConfig, Simulator};
+
+#[derive(Parser)]
+#[command(author, version, about = "Simulate AI-driven encounters between parties", long_about = None)]
+struct Cli {
+    /// Party files (YAML, or JSON with a .json extension)
+    #[arg(required = true, num_args = 2..)]
+    parties: Vec<String>,
+
+    #[arg(short = 'n', long, default_value_t = 1000)]
+    encounters: usize,
+
+    #[arg(short = 'r', long, default_value_t = 50)]
+    max_rounds: u32,
+
+    #[arg(short = 'd', long, default_value_t = 0)]
+    seed: u64,
+
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+fn load_party(path: &str) -> Result<Party, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let is_json = Path::new(path).extension().is_some_and(|ext| ext == "json");
+    let party = if is_json {
+        Party::from_json(&contents).map_err(|e| e.to_string())
+    } else {
+        Party::from_yaml(&contents).map_err(|e| e.to_string())
+    };
+    party.map_err(|e| format!("{}: {}", path, e))
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut simulator = Simulator::new(SimulationConfig {
+        encounters: cli.encounters,
+        max_rounds: cli.max_rounds,
+        seed: cli.seed,
+    });
+    for path in &cli.parties {
+        match load_party(path) {
+            Ok(party) => simulator.add_party(party),
+            Err(e) => {
+                eprintln!("Failed to load party {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let report = simulator.run();
+    if cli.json {
+        println!("{}", report.to_json().expect("report serializes"));
+    } else {
+        print!("{}", report);
+    }
+}
@@ -1,15 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::{HexPosition, item::{Item, EquipmentSlot, RaceType, ItemType, EquipmentType, WeaponType}};
+use crate::{HexPosition, race::RaceDefinition, encumbrance::{Encumbrance, InventoryError}, skill::{Proficiency, Skill, Training}, spell::Spellcasting, class::{ClassFeature, ClassLevel, FeatureGrant}, dice::{DiceRoller, RollMode}, effect::{EffectDuration, EffectKind, EffectTick, MovementModifier, StatusEffect, TriggerTiming}, item::{Item, EquipmentSlot, RaceType, ItemType, EquipmentType, WeaponType, WeaponDamage, DamageDefenses, DamageType}};
 
-const INVENTORY_WEIGHT_LIMIT: f32 = 100.0;
+/// Reach in hexes of unarmed and melee attacks
+pub const MELEE_REACH: i32 = 1;
+/// Levels at which a character without a class gains an extra attack per Attack action
+pub const EXTRA_ATTACK_LEVELS: [i32; 3] = [5, 11, 20];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Character {
     pub id: String,
     pub name: String,
     pub race: RaceType,
+    /// Traits of a race loaded from data; empty for the built-in races
+    #[serde(default)]
+    pub race_traits: Option<RaceDefinition>,
     pub position: HexPosition,
     pub stats: CharacterStats,
     pub health: Health,
@@ -17,6 +23,29 @@ pub struct Character {
     pub inventory: Vec<Item>,
     pub equipment: HashMap<EquipmentSlot, Item>,
     pub level: i32,
+    /// Defenses from temporary sources such as spells and potions
+    #[serde(default)]
+    pub defenses: DamageDefenses,
+    #[serde(default)]
+    pub effects: Vec<StatusEffect>,
+    /// Abilities whose saving throws add the proficiency bonus
+    #[serde(default)]
+    pub save_proficiencies: HashSet<Ability>,
+    #[serde(default)]
+    pub skills: HashMap<Skill, Training>,
+    /// Armor, weapons and tools the character is trained with
+    #[serde(default)]
+    pub proficiencies: HashSet<Proficiency>,
+    #[serde(default)]
+    pub experience: u32,
+    /// Levels taken in each class, in the order the classes were first taken
+    #[serde(default)]
+    pub classes: Vec<ClassLevel>,
+    /// Class features unlocked so far
+    #[serde(default)]
+    pub features: Vec<ClassFeature>,
+    #[serde(default)]
+    pub spellcasting: Option<Spellcasting>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +58,57 @@ pub struct CharacterStats {
     pub charisma: i32,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Ability {
+    Strength,
+    Dexterity,
+    Constitution,
+    Intelligence,
+    Wisdom,
+    Charisma,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 6] = [
+        Ability::Strength,
+        Ability::Dexterity,
+        Ability::Constitution,
+        Ability::Intelligence,
+        Ability::Wisdom,
+        Ability::Charisma,
+    ];
+}
+
+impl CharacterStats {
+    pub fn score(&self, ability: Ability) -> i32 {
+        match ability {
+            Ability::Strength => self.strength,
+            Ability::Dexterity => self.dexterity,
+            Ability::Constitution => self.constitution,
+            Ability::Intelligence => self.intelligence,
+            Ability::Wisdom => self.wisdom,
+            Ability::Charisma => self.charisma,
+        }
+    }
+
+    pub fn score_mut(&mut self, ability: Ability) -> &mut i32 {
+        match ability {
+            Ability::Strength => &mut self.strength,
+            Ability::Dexterity => &mut self.dexterity,
+            Ability::Constitution => &mut self.constitution,
+            Ability::Intelligence => &mut self.intelligence,
+            Ability::Wisdom => &mut self.wisdom,
+            Ability::Charisma => &mut self.charisma,
+        }
+    }
+
+    /// Scores from one value per ability, in `Ability::ALL` order
+    pub fn from_scores(scores: [i32; 6]) -> Self {
+        let [strength, dexterity, constitution, intelligence, wisdom, charisma] = scores;
+        Self { strength, dexterity, constitution, intelligence, wisdom, charisma }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Health {
     pub current: i32,
@@ -41,6 +121,11 @@ pub struct Movement {
     pub remaining: i32,  // Remaining movement points this turn
 }
 
+/// Standard ability modifier for a score: (score - 10) / 2, rounded down
+pub fn ability_modifier(score: i32) -> i32 {
+    (score - 10).div_euclid(2)
+}
+
 impl Character {
     pub fn new(name: String, race: RaceType, stats: CharacterStats) -> Self {
         let health = Health {
@@ -48,20 +133,32 @@ impl Character {
             maximum: 10 + stats.constitution,
         };
 
+        let speed = RaceDefinition::builtin(&race).speed;
+
         Self {
             id: Uuid::new_v4().to_string(),
             name,
             race,
+            race_traits: None,
             position: HexPosition::new_2d(0, 0),
             stats,
             health,
             movement: Movement {
-                speed: 6,
-                remaining: 6,
+                speed,
+                remaining: speed,
             },
             inventory: Vec::new(),
             equipment: HashMap::new(),
             level: 1,
+            defenses: DamageDefenses::default(),
+            effects: Vec::new(),
+            save_proficiencies: HashSet::new(),
+            skills: HashMap::new(),
+            proficiencies: HashSet::new(),
+            experience: 0,
+            classes: Vec::new(),
+            features: Vec::new(),
+            spellcasting: None,
         }
     }
 
@@ -70,20 +167,153 @@ impl Character {
     }
 
     pub fn reset_movement(&mut self) {
-        self.movement.remaining = self.movement.speed;
+        self.movement.remaining = self.effective_speed();
+    }
+
+    /// Base speed less any encumbrance, then status effects: flat changes first, then multipliers
+    pub fn effective_speed(&self) -> i32 {
+        let base = (self.movement.speed - self.encumbrance().speed_penalty()).max(0);
+        let modifiers: Vec<MovementModifier> = self.effects.iter().filter_map(|e| e.movement).collect();
+        let (flat, scaling): (Vec<_>, Vec<_>) = modifiers
+            .into_iter()
+            .partition(|m| matches!(m, MovementModifier::Flat(_)));
+        flat.iter()
+            .chain(scaling.iter())
+            .fold(base, |speed, modifier| modifier.apply(speed))
+    }
+
+    /// Apply a status effect, merging with an active one of the same kind
+    pub fn add_effect(&mut self, effect: StatusEffect) {
+        match self.effects.iter_mut().find(|e| e.kind == effect.kind) {
+            Some(existing) => existing.stack_with(effect),
+            None => self.effects.push(effect),
+        }
+    }
+
+    pub fn remove_effect(&mut self, kind: &EffectKind) -> Option<StatusEffect> {
+        let index = self.effects.iter().position(|e| e.kind == *kind)?;
+        Some(self.effects.remove(index))
+    }
+
+    pub fn has_effect(&self, kind: &EffectKind) -> bool {
+        self.effects.iter().any(|e| e.kind == *kind)
+    }
+
+    /// False while an effect such as stun takes away the character's actions
+    pub fn can_act(&self) -> bool {
+        self.is_alive() && !self.effects.iter().any(|e| e.incapacitated)
+    }
+
+    /// Advantage/disadvantage on this character's attacks from their effects
+    pub fn attack_mode(&self) -> RollMode {
+        let armor = self.untrained_armor().map(|_| RollMode::Disadvantage);
+        let load = (self.encumbrance() == Encumbrance::HeavilyEncumbered).then_some(RollMode::Disadvantage);
+        RollMode::from_sources(self.effects.iter().map(|e| e.attack_mode).chain(armor).chain(load))
+    }
+
+    /// Advantage/disadvantage granted to attacks against this character
+    pub fn incoming_attack_mode(&self) -> RollMode {
+        RollMode::from_sources(self.effects.iter().map(|e| e.incoming_attack_mode))
+    }
+
+    /// Run start-of-turn effect triggers
+    pub fn start_turn_effects(&mut self, roller: &mut dyn DiceRoller) -> Vec<EffectTick> {
+        self.run_effect_triggers(TriggerTiming::TurnStart, roller)
+    }
+
+    /// Run end-of-turn effect triggers, then count down and expire durations
+    pub fn end_turn_effects(&mut self, roller: &mut dyn DiceRoller) -> Vec<EffectTick> {
+        let mut ticks = self.run_effect_triggers(TriggerTiming::TurnEnd, roller);
+
+        for effect in self.effects.iter_mut() {
+            if let EffectDuration::Rounds(rounds) = &mut effect.duration {
+                *rounds = rounds.saturating_sub(1);
+            }
+        }
+        self.effects.retain(|effect| {
+            if effect.is_expired() {
+                ticks.push(EffectTick::Expired { kind: effect.kind.clone() });
+                false
+            } else {
+                true
+            }
+        });
+
+        ticks
     }
 
-    pub fn current_weight(&self) -> f32 {
-        self.inventory.iter().map(|item| item.weight).sum()
+    fn run_effect_triggers(&mut self, timing: TriggerTiming, roller: &mut dyn DiceRoller) -> Vec<EffectTick> {
+        let fired: Vec<EffectTick> = self
+            .effects
+            .iter()
+            .flat_map(|effect| effect.fire_triggers(timing, roller))
+            .collect();
+
+        fired
+            .into_iter()
+            .map(|tick| match tick {
+                EffectTick::Damaged { kind, amount, damage_type } => EffectTick::Damaged {
+                    kind,
+                    amount: self.take_damage(amount, damage_type),
+                    damage_type,
+                },
+                EffectTick::Healed { kind, amount } => EffectTick::Healed { kind, amount: self.heal(amount) },
+                other => other,
+            })
+            .collect()
+    }
+
+    /// Restore hit points up to the maximum, returning how many were restored
+    pub fn heal(&mut self, amount: i32) -> i32 {
+        let healed = amount.min(self.health.maximum - self.health.current).max(0);
+        self.health.current += healed;
+        healed
+    }
+
+    /// Consume an inventory item such as a potion, returning the hit points or mana restored
+    pub fn use_consumable(&mut self, item_id: &str, roller: &mut dyn DiceRoller) -> Result<i32, InventoryError> {
+        let item = self
+            .inventory
+            .iter()
+            .find(|item| item.id == item_id)
+            .ok_or(InventoryError::NotInInventory)?;
+        let (healing, mana) = match &item.item_type {
+            ItemType::Consumable(consumable) => (consumable.healing(), consumable.mana()),
+            _ => return Err(InventoryError::NotConsumable),
+        };
+        // Mana is wasted on someone who cannot cast
+        let mana = mana.filter(|_| self.spellcasting.is_some());
+        if healing.is_none() && mana.is_none() {
+            return Err(InventoryError::NoEffect(item.name.clone()));
+        }
+
+        self.remove_from_inventory(item_id);
+        if let Some(healing) = healing {
+            let amount = healing.evaluate(roller).total.max(0);
+            return Ok(self.heal(amount));
+        }
+        let amount = mana.map_or(0, |mana| mana.evaluate(roller).total.max(0));
+        Ok(self.restore_mana(amount as u32) as i32)
+    }
+
+    /// First consumable in the inventory that restores hit points
+    pub fn healing_item(&self) -> Option<&Item> {
+        self.inventory.iter().find(|item| match &item.item_type {
+            ItemType::Consumable(consumable) => consumable.healing().is_some(),
+            _ => false,
+        })
     }
 
     pub fn can_carry(&self, item: &Item) -> bool {
-        self.current_weight() + item.weight <= INVENTORY_WEIGHT_LIMIT
+        self.current_weight() + item.weight <= self.carrying_capacity()
     }
 
-    pub fn add_to_inventory(&mut self, item: Item) -> Result<(), String> {
+    pub fn add_to_inventory(&mut self, item: Item) -> Result<(), InventoryError> {
         if !self.can_carry(&item) {
-            return Err("Cannot carry more weight".to_string());
+            return Err(InventoryError::TooHeavy {
+                weight: self.current_weight() + item.weight,
+                capacity: self.carrying_capacity(),
+            });
         }
         self.inventory.push(item);
         Ok(())
@@ -97,6 +327,10 @@ impl Character {
         }
     }
 
+    /// Equip an item from the inventory into its slot
+    ///
+    /// Gear the character is not trained with can still be worn, at a
+    /// penalty; see `untrained_armor` and `weapon_proficiency_bonus`.
     pub fn equip_item(&mut self, item_id: &str) -> Result<(), String> {
         let item_pos = self.inventory
             .iter()
@@ -174,20 +408,94 @@ impl Character {
     }
 
     pub fn unequip_item(&mut self, slot: &EquipmentSlot) -> Result<(), String> {
+        // Worn gear already counts toward the carried weight, so it always fits
         if let Some(item) = self.equipment.remove(slot) {
-            if self.can_carry(&item) {
-                self.inventory.push(item);
-                Ok(())
-            } else {
-                // Put the item back if we can't carry it
-                self.equipment.insert(*slot, item);
-                Err("Inventory too full to unequip".to_string())
-            }
+            self.inventory.push(item);
+            Ok(())
         } else {
             Err("No item equipped in that slot".to_string())
         }
     }
 
+    /// Damage profile of the weapon equipped in a hand slot, if any
+    pub fn weapon(&self, slot: EquipmentSlot) -> Option<&WeaponDamage> {
+        self.equipment
+            .get(&slot)
+            .and_then(|item| item.stats.as_ref())
+            .and_then(|stats| stats.damage.as_ref())
+    }
+
+    /// Attacks made with one Attack action: one, plus one per Extra Attack feature
+    ///
+    /// Characters without a class gain theirs at the `EXTRA_ATTACK_LEVELS`.
+    pub fn attacks_per_action(&self) -> u32 {
+        let extra = if self.classes.is_empty() {
+            EXTRA_ATTACK_LEVELS.iter().filter(|&&level| self.level >= level).count()
+        } else {
+            self.features.iter().filter(|f| f.grant == Some(FeatureGrant::ExtraAttack)).count()
+        };
+        1 + extra as u32
+    }
+
+    /// How far away this character can attack with their main-hand weapon
+    pub fn attack_range(&self) -> i32 {
+        self.weapon_range(EquipmentSlot::MainHand)
+    }
+
+    pub fn weapon_range(&self, slot: EquipmentSlot) -> i32 {
+        self.weapon(slot)
+            .and_then(|damage| damage.range)
+            .unwrap_or(MELEE_REACH)
+    }
+
+    /// Ability modifier used to attack and deal damage with a weapon
+    ///
+    /// Ranged weapons use dexterity, finesse weapons the better of strength
+    /// and dexterity, and everything else (including fists) strength.
+    pub fn weapon_ability_modifier(&self, weapon: Option<&WeaponDamage>) -> i32 {
+        let stats = self.get_total_stats();
+        let strength = ability_modifier(stats.strength);
+        let dexterity = ability_modifier(stats.dexterity);
+        match weapon {
+            Some(damage) if damage.is_ranged() => dexterity,
+            Some(damage) if damage.finesse => strength.max(dexterity),
+            _ => strength,
+        }
+    }
+
+    /// Combined defenses from race, equipped items and temporary sources
+    pub fn damage_defenses(&self) -> DamageDefenses {
+        let mut total = self.race_definition().defenses.clone();
+        for item in self.equipment.values() {
+            if let Some(stats) = &item.stats {
+                total.merge(&stats.defenses);
+            }
+        }
+        total.merge(&self.defenses);
+        for effect in &self.effects {
+            total.merge(&effect.defenses);
+        }
+        total
+    }
+
+    /// Apply incoming damage after defenses, returning the amount actually taken
+    pub fn take_damage(&mut self, damage: i32, damage_type: DamageType) -> i32 {
+        let taken = self.damage_defenses().apply(damage.max(0), damage_type);
+        self.health.current -= taken;
+        taken
+    }
+
+    /// 10 + dexterity modifier + armor from every equipped item
+    pub fn armor_class(&self) -> i32 {
+        let armor: i32 = self
+            .equipment
+            .values()
+            .filter_map(|item| item.stats.as_ref())
+            .map(|stats| stats.armor)
+            .sum();
+        10 + ability_modifier(self.get_total_stats().dexterity) + armor
+    }
+
     pub fn get_total_stats(&self) -> CharacterStats {
         let mut total = self.stats.clone();
 
@@ -203,6 +511,10 @@ impl Character {
             }
         }
 
+        for effect in &self.effects {
+            effect.apply_to_stats(&mut total);
+        }
+
         total
     }
 }
@@ -225,4 +537,60 @@ mod tests {
         assert_eq!(character.health.maximum, 20);
         assert!(character.is_alive());
     }
+
+    #[test]
+    fn test_status_effects() {
+        use crate::dice::ScriptedRoller;
+        use crate::effect::StatModifiers;
+
+        let stats = CharacterStats {
+            strength: 10,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10,
+        };
+        let mut character = Character::new("Test Character".to_string(), RaceType::Human, stats);
+
+        let mut weakened = StatusEffect::new(EffectKind::Custom("Weakened".to_string()), EffectDuration::Rounds(1));
+        weakened.stat_modifiers = StatModifiers { strength: -2, ..Default::default() };
+        character.add_effect(weakened);
+        character.add_effect(StatusEffect::prone());
+        character.add_effect(StatusEffect::burning(2));
+
+        assert_eq!(character.get_total_stats().strength, 8);
+        assert_eq!(character.effective_speed(), 3);
+
+        let ticks = character.start_turn_effects(&mut ScriptedRoller::new([4]));
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(character.health.current, 16);
+
+        // The one-round effect expires at the end of the turn; burning has one round left
+        let ticks = character.end_turn_effects(&mut ScriptedRoller::new([]));
+        assert_eq!(ticks, vec![EffectTick::Expired { kind: EffectKind::Custom("Weakened".to_string()) }]);
+        assert_eq!(character.get_total_stats().strength, 10);
+        assert!(character.has_effect(&EffectKind::Burning));
+    }
+
+    #[test]
+    fn test_use_consumable_errors() {
+        use crate::dice::ScriptedRoller;
+        use crate::item::ConsumableType;
+
+        let stats = CharacterStats::from_scores([10, 10, 10, 10, 10, 10]);
+        let mut character = Character::new("Test Character".to_string(), RaceType::Human, stats);
+        let mana = Item::new_consumable("Mana Potion".to_string(), ConsumableType::ManaPotion, String::new());
+        let mana_id = mana.id.clone();
+        character.add_to_inventory(mana).unwrap();
+
+        let mut roller = ScriptedRoller::new([]);
+        assert_eq!(character.use_consumable("missing", &mut roller), Err(InventoryError::NotInInventory));
+        // Someone without spellcasting gets nothing from mana, and keeps the potion
+        assert_eq!(
+            character.use_consumable(&mana_id, &mut roller),
+            Err(InventoryError::NoEffect("Mana Potion".to_string()))
+        );
+        assert_eq!(character.inventory.len(), 1);
+    }
 }
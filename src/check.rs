@@ -0,0 +1,248 @@
+//! Ability checks, skill checks, saving throws and contests between characters
+
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character,
+    character::{Ability, ability_modifier},
+    dice::{self, DiceRoller, RollMode, RollOptions, RollResult},
+    encumbrance::Encumbrance,
+    skill::{Skill, Training},
+};
+
+/// Bonus for proficient checks and saves: +2 at 1st level, one more every four levels
+pub fn proficiency_bonus(level: i32) -> i32 {
+    2 + (level.max(1) - 1) / 4
+}
+
+/// The usual difficulty classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Difficulty {
+    VeryEasy,
+    Easy,
+    Medium,
+    Hard,
+    VeryHard,
+    NearlyImpossible,
+}
+
+impl Difficulty {
+    pub fn dc(self) -> i32 {
+        match self {
+            Difficulty::VeryEasy => 5,
+            Difficulty::Easy => 10,
+            Difficulty::Medium => 15,
+            Difficulty::Hard => 20,
+            Difficulty::VeryHard => 25,
+            Difficulty::NearlyImpossible => 30,
+        }
+    }
+}
+
+/// What a d20 test is rolled against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Check {
+    Ability(Ability),
+    Save(Ability),
+    Skill(Skill),
+}
+
+impl Check {
+    pub fn ability(self) -> Ability {
+        match self {
+            Check::Ability(ability) | Check::Save(ability) => ability,
+            Check::Skill(skill) => skill.ability(),
+        }
+    }
+}
+
+/// Outcome of a check or save against a difficulty class
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub roll: RollResult,
+    pub dc: i32,
+    pub success: bool,
+}
+
+/// What a successful saving throw does to the damage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SaveOutcome {
+    #[default]
+    HalfDamage,
+    NoDamage,
+}
+
+/// Two opposed checks, such as a grapple or stealth against perception
+///
+/// The initiator has to beat the opponent; a tie leaves things as they were.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contest {
+    pub initiator: RollResult,
+    pub opponent: RollResult,
+}
+
+impl Contest {
+    pub fn initiator_wins(&self) -> bool {
+        self.initiator.value > self.opponent.value
+    }
+}
+
+impl Character {
+    /// Modifier of an ability score, equipment and effects included
+    pub fn modifier(&self, ability: Ability) -> i32 {
+        ability_modifier(self.get_total_stats().score(ability))
+    }
+
+    pub fn proficiency_bonus(&self) -> i32 {
+        proficiency_bonus(self.level)
+    }
+
+    pub fn is_proficient(&self, check: Check) -> bool {
+        match check {
+            Check::Save(ability) => self.save_proficiencies.contains(&ability),
+            Check::Skill(skill) => self.skills.contains_key(&skill),
+            Check::Ability(_) => false,
+        }
+    }
+
+    /// Everything added to the d20: the ability modifier, plus proficiency
+    /// (doubled by expertise) where it applies
+    pub fn check_modifier(&self, check: Check) -> i32 {
+        let multiplier = match check {
+            Check::Skill(skill) => self.skill_training(skill).map_or(0, Training::multiplier),
+            _ if self.is_proficient(check) => 1,
+            _ => 0,
+        };
+        self.modifier(check.ability()) + self.proficiency_bonus() * multiplier
+    }
+
+    /// `mode` combined with disadvantage on strength and dexterity rolls
+    /// while wearing armor the character is not trained for, and on
+    /// constitution rolls as well while heavily encumbered
+    pub fn check_mode(&self, check: Check, mode: RollMode) -> RollMode {
+        let physical = matches!(check.ability(), Ability::Strength | Ability::Dexterity);
+        let strained = matches!(check.ability(), Ability::Strength | Ability::Dexterity | Ability::Constitution)
+            && self.encumbrance() == Encumbrance::HeavilyEncumbered;
+        if (physical && self.untrained_armor().is_some()) || strained {
+            mode.combine(RollMode::Disadvantage)
+        } else {
+            mode
+        }
+    }
+
+    /// Roll a check without a difficulty class, e.g. for initiative or a contest
+    pub fn roll_check(&self, check: Check, mode: RollMode, roller: &mut dyn DiceRoller) -> RollResult {
+        let options = RollOptions { mode: self.check_mode(check, mode), ..Default::default() };
+        dice::roll_with(roller, 1, 20, self.check_modifier(check), options)
+    }
+
+    /// Roll a check against `dc`, succeeding on a total of at least `dc`
+    ///
+    /// An incapacitated character still rolls but automatically fails
+    /// strength and dexterity saves.
+    pub fn attempt(&self, check: Check, dc: i32, mode: RollMode, roller: &mut dyn DiceRoller) -> CheckResult {
+        let roll = self.roll_check(check, mode, roller);
+        let helpless = !self.can_act()
+            && matches!(check, Check::Save(Ability::Strength) | Check::Save(Ability::Dexterity));
+        CheckResult {
+            check,
+            success: !helpless && roll.value >= dc,
+            roll,
+            dc,
+        }
+    }
+
+    pub fn ability_check(&self, ability: Ability, dc: i32, roller: &mut dyn DiceRoller) -> CheckResult {
+        self.attempt(Check::Ability(ability), dc, RollMode::Normal, roller)
+    }
+
+    pub fn saving_throw(&self, ability: Ability, dc: i32, roller: &mut dyn DiceRoller) -> CheckResult {
+        self.attempt(Check::Save(ability), dc, RollMode::Normal, roller)
+    }
+
+    /// Pit one of this character's checks against one of `opponent`'s
+    pub fn contest(&self, check: Check, opponent: &Character, opposed: Check, roller: &mut dyn DiceRoller) -> Contest {
+        Contest {
+            initiator: self.roll_check(check, RollMode::Normal, roller),
+            opponent: opponent.roll_check(opposed, RollMode::Normal, roller),
+        }
+    }
+
+    /// Athletics against the target's better of athletics and acrobatics
+    pub fn grapple(&self, target: &Character, roller: &mut dyn DiceRoller) -> Contest {
+        let athletics = Check::Skill(Skill::Athletics);
+        let acrobatics = Check::Skill(Skill::Acrobatics);
+        let escape = if target.check_modifier(acrobatics) > target.check_modifier(athletics) {
+            acrobatics
+        } else {
+            athletics
+        };
+        self.contest(athletics, target, escape, roller)
+    }
+
+    /// Stealth to stay hidden against the seeker's perception
+    pub fn hide_from(&self, seeker: &Character, roller: &mut dyn DiceRoller) -> Contest {
+        self.contest(Check::Skill(Skill::Stealth), seeker, Check::Skill(Skill::Perception), roller)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        character::CharacterStats,
+        dice::ScriptedRoller,
+        effect::StatusEffect,
+        item::RaceType,
+    };
+
+    fn character(strength: i32, dexterity: i32) -> Character {
+        let stats = CharacterStats {
+            strength,
+            dexterity,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 9,
+            charisma: 3,
+        };
+        Character::new("Tester".to_string(), RaceType::Human, stats)
+    }
+
+    #[test]
+    fn test_modifiers_and_proficiency() {
+        let mut hero = character(16, 10);
+        // Odd low scores round down
+        assert_eq!(hero.modifier(Ability::Wisdom), -1);
+        assert_eq!(hero.modifier(Ability::Charisma), -4);
+
+        let bonuses: Vec<i32> = [1, 4, 5, 9, 13, 17, 20].iter().map(|&l| proficiency_bonus(l)).collect();
+        assert_eq!(bonuses, vec![2, 2, 3, 4, 5, 6, 6]);
+
+        hero.level = 5;
+        hero.save_proficiencies.insert(Ability::Strength);
+        assert_eq!(hero.check_modifier(Check::Save(Ability::Strength)), 6);
+        assert_eq!(hero.check_modifier(Check::Ability(Ability::Strength)), 3);
+
+        let mut roller = ScriptedRoller::new([9, 9]);
+        assert!(hero.saving_throw(Ability::Strength, Difficulty::Medium.dc(), &mut roller).success);
+        assert!(!hero.ability_check(Ability::Strength, Difficulty::Medium.dc(), &mut roller).success);
+
+        // Stunned creatures fail dexterity saves however well they roll
+        hero.add_effect(StatusEffect::stunned(1));
+        let save = hero.saving_throw(Ability::Dexterity, 5, &mut ScriptedRoller::new([20]));
+        assert!(!save.success);
+    }
+
+    #[test]
+    fn test_contests() {
+        let wrestler = character(16, 10);
+        let acrobat = character(8, 14);
+        // Strength 15 + 3 against the acrobat's dexterity 16 + 2: a tie holds
+        let grapple = wrestler.grapple(&acrobat, &mut ScriptedRoller::new([15, 16]));
+        assert_eq!((grapple.initiator.value, grapple.opponent.value), (18, 18));
+        assert!(!grapple.initiator_wins());
+
+        let hide = acrobat.hide_from(&wrestler, &mut ScriptedRoller::new([10, 10]));
+        assert!(hide.initiator_wins());
+    }
+}
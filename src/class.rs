@@ -0,0 +1,459 @@
+//! Character classes, experience and level progression
+
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character,
+    character::Ability,
+    dice::{self, DiceRoller},
+    item::{EquipmentType, Item, ItemStats, WeaponDamage},
+    skill::{Proficiency, Skill, Training},
+    spell::ClassSpellcasting,
+};
+
+/// Highest level a character can reach, across all of their classes
+pub const MAX_LEVEL: i32 = 20;
+
+/// Experience needed to reach each level, starting with level 1
+pub const XP_THRESHOLDS: [u32; MAX_LEVEL as usize] = [
+    0, 300, 900, 2_700, 6_500, 14_000, 23_000, 34_000, 48_000, 64_000,
+    85_000, 100_000, 120_000, 140_000, 165_000, 195_000, 225_000, 265_000, 305_000, 355_000,
+];
+
+/// The classes shipped in `classes/`
+const DEFAULT_CLASSES: [&str; 4] = [
+    include_str!("../classes/fighter.yaml"),
+    include_str!("../classes/rogue.yaml"),
+    include_str!("../classes/wizard.yaml"),
+    include_str!("../classes/cleric.yaml"),
+];
+
+/// The level a character with `experience` points has earned
+pub fn level_for_experience(experience: u32) -> i32 {
+    XP_THRESHOLDS.iter().filter(|&&needed| experience >= needed).count() as i32
+}
+
+/// Experience needed to reach `level`
+pub fn experience_for_level(level: i32) -> u32 {
+    XP_THRESHOLDS[(level.clamp(1, MAX_LEVEL) - 1) as usize]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Sides of the die rolled for hit points on each new level
+    pub hit_die: i32,
+    pub primary_ability: Ability,
+    /// Saving throws gained by taking this as the first class
+    #[serde(default)]
+    pub saving_throws: Vec<Ability>,
+    /// Skills the class trains, on top of any from race or background
+    #[serde(default)]
+    pub skills: Vec<Skill>,
+    /// Armor, weapon and tool training that comes with the class
+    #[serde(default)]
+    pub proficiencies: Vec<Proficiency>,
+    /// Scores needed to multiclass into or out of this class
+    #[serde(default)]
+    pub multiclass_requirements: Vec<AbilityRequirement>,
+    #[serde(default)]
+    pub features: Vec<ClassFeature>,
+    /// Gear a new character of this class is handed, equipped in order
+    #[serde(default)]
+    pub starting_equipment: Vec<StartingItem>,
+    /// Spells the class can cast and what it spends to cast them
+    #[serde(default)]
+    pub spellcasting: Option<ClassSpellcasting>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AbilityRequirement {
+    pub ability: Ability,
+    pub minimum: i32,
+}
+
+/// Something a class grants on reaching a class level
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassFeature {
+    /// Class level the feature is unlocked at
+    pub level: i32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The rules change the feature makes, if the engine models it
+    #[serde(default)]
+    pub grant: Option<FeatureGrant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeatureGrant {
+    /// One more attack per Attack action
+    ExtraAttack,
+    SaveProficiency(Ability),
+    /// Double the proficiency bonus on a skill
+    Expertise(Skill),
+}
+
+/// A piece of starting gear, described just enough to make an `Item`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartingItem {
+    pub name: String,
+    pub equipment_type: EquipmentType,
+    #[serde(default)]
+    pub armor: i32,
+    #[serde(default)]
+    pub damage: Option<WeaponDamage>,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub requires: Option<Proficiency>,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Levels a character has in one class
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassLevel {
+    pub class: String,
+    pub level: i32,
+}
+
+/// What a character gained from a new level
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelUp {
+    pub class: String,
+    pub class_level: i32,
+    pub level: i32,
+    /// Added to both current and maximum hit points
+    pub hit_points: i32,
+    pub features: Vec<ClassFeature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassError {
+    UnknownClass(String),
+    /// `Character::start_class` was called on a character who already has one
+    AlreadyHasClass,
+    /// Levelling up needs a starting class first
+    NoClass,
+    MaxLevel,
+    NotEnoughExperience { needed: u32, current: u32 },
+    /// A score is too low to multiclass into or out of `class`
+    MulticlassRequirement { class: String, requirement: AbilityRequirement },
+}
+
+impl ClassDefinition {
+    /// Features unlocked on reaching exactly `class_level`
+    pub fn features_at(&self, class_level: i32) -> impl Iterator<Item = &ClassFeature> {
+        self.features.iter().filter(move |f| f.level == class_level)
+    }
+}
+
+impl StartingItem {
+    /// A fresh item any race can use
+    pub fn to_item(&self) -> Item {
+        let stats = ItemStats {
+            strength_bonus: 0,
+            dexterity_bonus: 0,
+            constitution_bonus: 0,
+            intelligence_bonus: 0,
+            wisdom_bonus: 0,
+            charisma_bonus: 0,
+            armor: self.armor,
+            damage: self.damage.clone(),
+            defenses: Default::default(),
+        };
+        let mut item = Item::new_equipment(
+            self.name.clone(),
+            self.equipment_type.clone(),
+            stats,
+            None,
+            1,
+        );
+        item.weight = self.weight;
+        item.requires = self.requires.clone();
+        item
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ClassRegistry {
+    classes: HashMap<String, ClassDefinition>,
+}
+
+impl ClassRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the fighter, rogue, wizard and cleric loaded
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        for yaml in DEFAULT_CLASSES {
+            registry.load_class(yaml).expect("built-in classes are valid");
+        }
+        registry
+    }
+
+    /// Add a class from YAML, replacing any with the same name
+    pub fn load_class(&mut self, yaml: &str) -> Result<(), serde_yaml::Error> {
+        let class: ClassDefinition = serde_yaml::from_str(yaml)?;
+        self.classes.insert(class.name.clone(), class);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassDefinition> {
+        self.classes.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.classes.keys().map(|name| name.as_str())
+    }
+
+    fn lookup(&self, name: &str) -> Result<&ClassDefinition, ClassError> {
+        self.get(name).ok_or_else(|| ClassError::UnknownClass(name.to_string()))
+    }
+}
+
+impl Character {
+    /// Levels taken in `class`, zero if none
+    pub fn class_level(&self, class: &str) -> i32 {
+        self.classes.iter().find(|c| c.class == class).map_or(0, |c| c.level)
+    }
+
+    /// Add experience, returning whether it earned a new level
+    pub fn gain_experience(&mut self, amount: u32) -> bool {
+        self.experience = self.experience.saturating_add(amount);
+        self.can_level_up()
+    }
+
+    pub fn can_level_up(&self) -> bool {
+        !self.classes.is_empty() && self.level < level_for_experience(self.experience)
+    }
+
+    /// Take a first class at the character's current level
+    ///
+    /// Grants the class's saving throws, skills and proficiencies, and every
+    /// feature up to that level.
+    /// Hit points are left alone: those from `Character::new` stand in for
+    /// the levels already held.
+    pub fn start_class(&mut self, class: &str, registry: &ClassRegistry) -> Result<Vec<ClassFeature>, ClassError> {
+        if !self.classes.is_empty() {
+            return Err(ClassError::AlreadyHasClass);
+        }
+        let definition = registry.lookup(class)?;
+        self.classes.push(ClassLevel { class: definition.name.clone(), level: self.level });
+        self.save_proficiencies.extend(definition.saving_throws.iter().copied());
+        for &skill in &definition.skills {
+            self.train_skill(skill, Training::Proficient);
+        }
+        self.proficiencies.extend(definition.proficiencies.iter().cloned());
+        self.experience = self.experience.max(experience_for_level(self.level));
+
+        let features: Vec<ClassFeature> = definition
+            .features
+            .iter()
+            .filter(|f| f.level <= self.level)
+            .cloned()
+            .collect();
+        for feature in &features {
+            self.unlock(feature);
+        }
+        self.advance_spellcasting(registry);
+        Ok(features)
+    }
+
+    /// Spend earned experience on a level in `class`, multiclassing if it is new
+    ///
+    /// Hit points grow by the class's hit die plus the constitution modifier,
+    /// at least one. Multiclassing needs the requirements of every class held
+    /// as well as the new one, and grants no extra saving throws.
+    pub fn level_up(
+        &mut self,
+        class: &str,
+        registry: &ClassRegistry,
+        roller: &mut dyn DiceRoller,
+    ) -> Result<LevelUp, ClassError> {
+        if self.classes.is_empty() {
+            return Err(ClassError::NoClass);
+        }
+        if self.level >= MAX_LEVEL {
+            return Err(ClassError::MaxLevel);
+        }
+        let needed = experience_for_level(self.level + 1);
+        if self.experience < needed {
+            return Err(ClassError::NotEnoughExperience { needed, current: self.experience });
+        }
+        let definition = registry.lookup(class)?;
+        if self.class_level(&definition.name) == 0 {
+            self.check_multiclass(definition, registry)?;
+            self.classes.push(ClassLevel { class: definition.name.clone(), level: 0 });
+        }
+
+        let entry = self
+            .classes
+            .iter_mut()
+            .find(|c| c.class == definition.name)
+            .expect("class was just added");
+        entry.level += 1;
+        let class_level = entry.level;
+        self.level += 1;
+
+        let rolled = dice::roll(roller, 1, definition.hit_die, self.modifier(Ability::Constitution));
+        let hit_points = rolled.value.max(1);
+        self.health.maximum += hit_points;
+        self.health.current += hit_points;
+
+        let features: Vec<ClassFeature> = definition.features_at(class_level).cloned().collect();
+        for feature in &features {
+            self.unlock(feature);
+        }
+        self.advance_spellcasting(registry);
+        Ok(LevelUp {
+            class: definition.name.clone(),
+            class_level,
+            level: self.level,
+            hit_points,
+            features,
+        })
+    }
+
+    fn check_multiclass(&self, new_class: &ClassDefinition, registry: &ClassRegistry) -> Result<(), ClassError> {
+        let held = self.classes.iter().filter_map(|c| registry.get(&c.class));
+        for definition in held.chain(std::iter::once(new_class)) {
+            if let Some(requirement) = definition
+                .multiclass_requirements
+                .iter()
+                .find(|r| self.stats.score(r.ability) < r.minimum)
+            {
+                return Err(ClassError::MulticlassRequirement {
+                    class: definition.name.clone(),
+                    requirement: *requirement,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self, feature: &ClassFeature) {
+        match feature.grant {
+            Some(FeatureGrant::SaveProficiency(ability)) => {
+                self.save_proficiencies.insert(ability);
+            }
+            Some(FeatureGrant::Expertise(skill)) => self.train_skill(skill, Training::Expertise),
+            Some(FeatureGrant::ExtraAttack) | None => {}
+        }
+        self.features.push(feature.clone());
+    }
+}
+
+impl fmt::Display for ClassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClassError::UnknownClass(name) => write!(f, "no class named {}", name),
+            ClassError::AlreadyHasClass => write!(f, "character already has a starting class"),
+            ClassError::NoClass => write!(f, "character has no class to level up"),
+            ClassError::MaxLevel => write!(f, "character is already level {}", MAX_LEVEL),
+            ClassError::NotEnoughExperience { needed, current } => {
+                write!(f, "needs {} experience to level up, has {}", needed, current)
+            }
+            ClassError::MulticlassRequirement { class, requirement } => write!(
+                f,
+                "multiclassing with {} needs {:?} {}",
+                class, requirement.ability, requirement.minimum
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ClassError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{character::CharacterStats, dice::ScriptedRoller, item::RaceType};
+
+    fn character(strength: i32, intelligence: i32) -> Character {
+        let stats = CharacterStats {
+            strength,
+            dexterity: 12,
+            constitution: 14,
+            intelligence,
+            wisdom: 10,
+            charisma: 10,
+        };
+        Character::new("Hero".to_string(), RaceType::Human, stats)
+    }
+
+    #[test]
+    fn test_level_progression() {
+        assert_eq!(level_for_experience(0), 1);
+        assert_eq!(level_for_experience(299), 1);
+        assert_eq!(level_for_experience(6_500), 5);
+        assert_eq!(level_for_experience(1_000_000), MAX_LEVEL);
+
+        let registry = ClassRegistry::with_defaults();
+        let mut names: Vec<&str> = registry.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["Cleric", "Fighter", "Rogue", "Wizard"]);
+
+        let mut hero = character(16, 10);
+        let mut roller = ScriptedRoller::new([7, 1, 10, 4]);
+        assert_eq!(hero.level_up("Fighter", &registry, &mut roller).unwrap_err(), ClassError::NoClass);
+        let first = hero.start_class("Fighter", &registry).unwrap();
+        assert_eq!(first[0].name, "Second Wind");
+        assert!(hero.save_proficiencies.contains(&Ability::Constitution));
+
+        assert!(!hero.gain_experience(299));
+        assert_eq!(
+            hero.level_up("Fighter", &registry, &mut roller).unwrap_err(),
+            ClassError::NotEnoughExperience { needed: 300, current: 299 }
+        );
+
+        // d10 plus the constitution modifier of 2, never less than one
+        assert!(hero.gain_experience(6_500));
+        let maximum = hero.health.maximum;
+        let gains: Vec<i32> = (0..4)
+            .map(|_| hero.level_up("Fighter", &registry, &mut roller).unwrap().hit_points)
+            .collect();
+        assert_eq!(gains, vec![9, 3, 12, 6]);
+        assert_eq!(hero.health.maximum, maximum + 30);
+        assert_eq!((hero.level, hero.class_level("Fighter")), (5, 5));
+        assert_eq!(hero.attacks_per_action(), 2);
+        assert!(!hero.can_level_up());
+    }
+
+    #[test]
+    fn test_multiclassing() {
+        let registry = ClassRegistry::with_defaults();
+        let mut hero = character(16, 10);
+        hero.start_class("Fighter", &registry).unwrap();
+        hero.gain_experience(XP_THRESHOLDS[3]);
+
+        let err = hero.level_up("Wizard", &registry, &mut ScriptedRoller::new([4])).unwrap_err();
+        assert_eq!(err.to_string(), "multiclassing with Wizard needs Intelligence 13");
+        hero.stats.intelligence = 13;
+        let gained = hero.level_up("Wizard", &registry, &mut ScriptedRoller::new([4])).unwrap();
+        assert_eq!((gained.class_level, gained.level), (1, 2));
+        assert!(gained.features.iter().any(|f| f.name == "Spellcasting"));
+        // Only the first class grants saving throws
+        assert!(!hero.save_proficiencies.contains(&Ability::Intelligence));
+
+        // Falling below a held class's requirement blocks further multiclassing
+        hero.stats.strength = 12;
+        assert!(matches!(
+            hero.level_up("Rogue", &registry, &mut ScriptedRoller::new([4])),
+            Err(ClassError::MulticlassRequirement { class, .. }) if class == "Fighter"
+        ));
+
+        let restored: Character = serde_json::from_str(&serde_json::to_string(&hero).unwrap()).unwrap();
+        assert_eq!(restored.classes, hero.classes);
+        assert_eq!(restored.features.len(), hero.features.len());
+        assert_eq!(restored.experience, XP_THRESHOLDS[3]);
+    }
+}
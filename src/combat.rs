@@ -1,96 +1,1071 @@
-use crate::{Character, dice};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use crate::{
+    Character, HexGrid, HexPosition,
+    character::Ability,
+    check::Check,
+    dice::{self, DiceRoller, RollMode, RollOptions, RollResult, SeededRoller},
+    effect::EffectTick,
+    encumbrance::InventoryError,
+    item::{DamageType, EquipmentSlot, ItemType, WeaponDamage},
+    spell::SpellError,
+};
+
+mod ai;
+mod area;
+mod economy;
+mod initiative;
+mod log;
+mod reaction;
+mod roster;
+mod snapshot;
+mod spellcasting;
+mod teams;
+#[cfg(test)]
+pub(crate) mod test_support;
+
+pub use ai::{AggressiveMelee, AiAction, CombatAi, DefensiveGuard, KitingRanged, Tactics};
+pub use area::{AreaEffect, AreaHit, AreaSave};
+pub use economy::{ActionError, ActionKind, TurnBudget};
+pub use initiative::{InitiativeEntry, TurnOrder};
+pub use log::{CombatEvent, CombatLog, LoggedEvent, ReplayedParticipant};
+pub use reaction::{Counterspell, OpportunityAttacks, Parry, Reaction, ReactionTrigger, Reactor, ShieldBlock};
+pub use roster::Roster;
+pub use snapshot::{CombatSnapshot, UNDO_LIMIT};
+pub use spellcasting::{SpellHit, SpellOutcome};
+pub use teams::{CombatOutcome, FriendlyFire, Relation, Teams, VictoryCondition};
+
+use snapshot::Checkpoint;
 
 pub struct Combat {
-    participants: Vec<Character>,
-    current_turn: usize,
+    participants: Roster,
+    turn_order: TurnOrder,
+    roller: Box<dyn DiceRoller>,
+    /// Shared with the undo checkpoints, as actions never change it
+    grid: Option<Rc<HexGrid>>,
+    log: CombatLog,
+    teams: Teams,
+    friendly_fire: FriendlyFire,
+    victory_conditions: Vec<VictoryCondition>,
+    outcome: Option<CombatOutcome>,
+    reactors: HashMap<String, Box<dyn Reactor>>,
+    /// Participants who have spent their reaction since their last turn began
+    reactions_used: HashSet<String>,
+    /// What the participant taking their turn has left to spend
+    budget: Option<TurnBudget>,
+    /// States before each recent action, most recent last, for `undo`
+    history: Vec<Checkpoint>,
+    undo_limit: usize,
+    /// Actions in progress, so nested ones are undone together
+    action_depth: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CombatError {
+    ParticipantNotFound,
+    SameParticipant,
+    AttackerDown,
+    TargetDown,
+    OutOfRange { distance: i32, range: i32 },
+    NoGrid,
+    NoPath,
+    HexOccupied(HexPosition),
+    NotEnoughMovement { required: i32, remaining: i32 },
+    NoOffHandWeapon,
+    Incapacitated,
+    NoActiveTurn,
+    CannotUseItem(String),
+    Inventory(InventoryError),
+    FriendlyFire,
+    EncounterOver,
+    NoLineOfSight,
+    Action(ActionError),
+    NotSaveable,
+    NothingToUndo,
+    Spell(SpellError),
+    /// A single-target spell was aimed at an empty hex
+    NoSpellTarget,
+    NotInTurnOrder,
+    /// A turn can only be delayed until after someone who acts later this round
+    CannotDelayPastSelf,
+    /// Only a turn nothing has been spent from can be delayed
+    AlreadyActed,
+    NoReadiedAction,
+}
+
+impl fmt::Display for CombatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CombatError::ParticipantNotFound => write!(f, "participant not found"),
+            CombatError::SameParticipant => write!(f, "a participant cannot target themselves"),
+            CombatError::AttackerDown => write!(f, "attacker is down"),
+            CombatError::TargetDown => write!(f, "target is already down"),
+            CombatError::OutOfRange { distance, range } => {
+                write!(f, "target is {} hexes away but range is {}", distance, range)
+            }
+            CombatError::NoGrid => write!(f, "combat has no grid to move on"),
+            CombatError::NoPath => write!(f, "no path to destination"),
+            CombatError::HexOccupied(pos) => write!(f, "hex ({}, {}) is occupied", pos.q, pos.r),
+            CombatError::NotEnoughMovement { required, remaining } => {
+                write!(f, "move costs {} but only {} movement remains", required, remaining)
+            }
+            CombatError::NoOffHandWeapon => write!(f, "no weapon held in the off hand"),
+            CombatError::Incapacitated => write!(f, "participant is incapacitated"),
+            CombatError::NoActiveTurn => write!(f, "no participant is taking a turn"),
+            CombatError::CannotUseItem(reason) => write!(f, "cannot use item: {}", reason),
+            CombatError::Inventory(error) => write!(f, "cannot use item: {}", error),
+            CombatError::FriendlyFire => write!(f, "friendly fire is not allowed"),
+            CombatError::EncounterOver => write!(f, "the encounter is over"),
+            CombatError::NoLineOfSight => write!(f, "no line of sight to the target"),
+            CombatError::Action(error) => write!(f, "{}", error),
+            CombatError::NotSaveable => write!(f, "the dice roller cannot be saved"),
+            CombatError::NothingToUndo => write!(f, "nothing to undo"),
+            CombatError::Spell(error) => write!(f, "{}", error),
+            CombatError::NoSpellTarget => write!(f, "no one stands where the spell is aimed"),
+            CombatError::NotInTurnOrder => write!(f, "participant is not in the initiative order"),
+            CombatError::CannotDelayPastSelf => {
+                write!(f, "can only delay until after a participant who acts later this round")
+            }
+            CombatError::AlreadyActed => write!(f, "cannot delay after acting or moving this turn"),
+            CombatError::NoReadiedAction => write!(f, "participant has no readied action"),
+        }
+    }
+}
+
+impl std::error::Error for CombatError {}
+
+impl From<ActionError> for CombatError {
+    fn from(error: ActionError) -> Self {
+        CombatError::Action(error)
+    }
+}
+
+impl From<InventoryError> for CombatError {
+    fn from(error: InventoryError) -> Self {
+        CombatError::Inventory(error)
+    }
+}
+
+impl From<SpellError> for CombatError {
+    fn from(error: SpellError) -> Self {
+        CombatError::Spell(error)
+    }
+}
+
+/// Upper bound on actions an AI may take in one turn, in case it never ends it
+const MAX_AI_ACTIONS: usize = 8;
+
+/// Fists: 1d6 blunt at melee reach
+const UNARMED: WeaponDamage = WeaponDamage {
+    min_damage: 1,
+    max_damage: 6,
+    damage_type: DamageType::Blunt,
+    range: None,
+    finesse: false,
+};
+
+/// Roll a weapon's damage uniformly between its minimum and maximum
+fn roll_weapon_damage(roller: &mut dyn DiceRoller, weapon: &WeaponDamage, modifier: i32) -> RollResult {
+    let sides = (weapon.max_damage - weapon.min_damage + 1).max(1);
+    dice::roll(roller, 1, sides, weapon.min_damage - 1 + modifier)
+}
+
+/// An attack roll once the defender has had the chance to react
+struct AttackRoll {
+    roll: RollResult,
+    /// Armor class after any reaction raised it
+    armor_class: i32,
+    hit: bool,
+    critical: bool,
+    /// Damage a reaction such as a parry takes off a hit
+    reduction: i32,
 }
 
 #[derive(Debug)]
 pub struct AttackResult {
     pub hit: bool,
+    /// Damage actually dealt after the defender's defenses
     pub damage: i32,
+    /// Damage rolled before resistances, vulnerabilities and immunities
+    pub raw_damage: i32,
+    /// `raw_damage - damage`; negative when a vulnerability added damage
+    pub mitigated: i32,
     pub critical: bool,
+    pub damage_type: DamageType,
+    pub attack_roll: RollResult,
+    pub damage_roll: Option<RollResult>,
 }
 
 impl Combat {
     pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_roller(Box::new(SeededRoller::new(seed)))
+    }
+
+    pub fn with_roller(roller: Box<dyn DiceRoller>) -> Self {
         Self {
-            participants: Vec::new(),
-            current_turn: 0,
+            participants: Roster::new(),
+            turn_order: TurnOrder::new(),
+            roller,
+            grid: None,
+            log: CombatLog::new(),
+            teams: Teams::new(),
+            friendly_fire: FriendlyFire::default(),
+            victory_conditions: Vec::new(),
+            outcome: None,
+            reactors: HashMap::new(),
+            reactions_used: HashSet::new(),
+            budget: None,
+            history: Vec::new(),
+            undo_limit: UNDO_LIMIT,
+            action_depth: 0,
         }
     }
 
+    /// Fight on a grid: movement follows its paths and costs
+    pub fn set_grid(&mut self, grid: HexGrid) {
+        self.grid = Some(Rc::new(grid));
+    }
+
+    pub fn grid(&self) -> Option<&HexGrid> {
+        self.grid.as_deref()
+    }
+
+    /// Everything that has happened in this encounter so far
+    pub fn log(&self) -> &CombatLog {
+        &self.log
+    }
+
+    fn record(&mut self, event: CombatEvent) {
+        let round = self.turn_order.round();
+        self.log.push(round, event);
+    }
+
+    fn record_effect_ticks(&mut self, id: &str, ticks: Vec<EffectTick>) {
+        for tick in ticks {
+            self.record(CombatEvent::EffectTicked { id: id.to_string(), tick });
+        }
+    }
+
+    /// Add a combatant, rolling initiative (a dexterity check) for them
     pub fn add_participant(&mut self, character: Character) {
-        self.participants.push(character);
+        let check = Check::Ability(Ability::Dexterity);
+        let roll = character.roll_check(check, RollMode::Normal, self.roller.as_mut());
+        self.add_participant_with_initiative(character, roll.value);
+    }
+
+    /// Add a combatant with an already-determined initiative total
+    ///
+    /// Adding a character whose id is already in the encounter replaces them,
+    /// initiative included, so nobody ends up taking two turns a round.
+    pub fn add_participant_with_initiative(&mut self, character: Character, initiative: i32) {
+        self.turn_order.remove(&character.id);
+        self.turn_order.insert(InitiativeEntry {
+            participant_id: character.id.clone(),
+            total: initiative,
+            dexterity: character.get_total_stats().dexterity,
+        });
+        self.record(CombatEvent::Joined {
+            id: character.id.clone(),
+            name: character.name.clone(),
+            initiative,
+            position: character.position,
+            health: character.health.current,
+            max_health: character.health.maximum,
+        });
+        self.participants.insert(character);
+    }
+
+    pub fn remove_participant(&mut self, id: &str) -> Option<Character> {
+        self.turn_order.remove(id);
+        let character = self.participants.remove(id)?;
+        self.teams.remove(id);
+        self.record(CombatEvent::Left { id: id.to_string() });
+        self.check_victory();
+        Some(character)
+    }
+
+    /// Everyone in the encounter, in joining order
+    pub fn participants(&self) -> &[Character] {
+        self.participants.as_slice()
+    }
+
+    /// Participants by id, with living and fallen views
+    pub fn roster(&self) -> &Roster {
+        &self.participants
+    }
+
+    pub fn get_participant(&self, id: &str) -> Option<&Character> {
+        self.participants.get(id)
+    }
+
+    /// Put a participant on a side; members of the same team do not fight each other
+    pub fn set_team(&mut self, id: &str, team: &str) {
+        self.teams.set_team(id, team);
+    }
+
+    pub fn team(&self, id: &str) -> Option<&str> {
+        self.teams.team(id)
+    }
+
+    pub fn teams(&self) -> &Teams {
+        &self.teams
+    }
+
+    /// Set how two teams regard each other; teams start out hostile
+    pub fn set_relation(&mut self, a: &str, b: &str, relation: Relation) {
+        self.teams.set_relation(a, b, relation);
+    }
+
+    /// Whether two participants are enemies; anyone without a team fights everyone
+    pub fn is_hostile(&self, a: &str, b: &str) -> bool {
+        self.teams.relation_between(a, b) == Relation::Hostile
+    }
+
+    pub fn is_allied(&self, a: &str, b: &str) -> bool {
+        self.teams.relation_between(a, b) == Relation::Allied
+    }
+
+    pub fn set_friendly_fire(&mut self, friendly_fire: FriendlyFire) {
+        self.friendly_fire = friendly_fire;
+    }
+
+    pub fn friendly_fire(&self) -> FriendlyFire {
+        self.friendly_fire
+    }
+
+    /// End the encounter when this condition is met; conditions are checked in the order added
+    pub fn add_victory_condition(&mut self, condition: VictoryCondition) {
+        self.victory_conditions.push(condition);
+    }
+
+    /// How the encounter ended, once it has
+    pub fn outcome(&self) -> Option<&CombatOutcome> {
+        self.outcome.as_ref()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    fn check_victory(&mut self) {
+        if self.outcome.is_some() {
+            return;
+        }
+        let reached = self
+            .victory_conditions
+            .iter()
+            .find_map(|condition| Some((self.winners_for(condition)?, condition.clone())));
+
+        if let Some((winners, condition)) = reached {
+            self.record(CombatEvent::Ended {
+                winners: winners.clone(),
+                condition: condition.clone(),
+            });
+            self.outcome = Some(CombatOutcome {
+                winners,
+                condition,
+                round: self.round(),
+            });
+        }
     }
 
+    /// The winning sides if `condition` has been met
+    fn winners_for(&self, condition: &VictoryCondition) -> Option<Vec<String>> {
+        let living: Vec<&Character> = self.participants.alive().collect();
+        let sides = |filter: &dyn Fn(&Character) -> bool| {
+            let mut sides: Vec<String> = self
+                .participants
+                .iter()
+                .filter(|c| filter(c))
+                .map(|c| self.teams.side(&c.id).to_string())
+                .collect();
+            sides.sort_unstable();
+            sides.dedup();
+            sides
+        };
+
+        match condition {
+            VictoryCondition::LastTeamStanding => {
+                let fighting = living
+                    .iter()
+                    .any(|a| living.iter().any(|b| self.is_hostile(&a.id, &b.id)));
+                (!fighting).then(|| sides(&|c| c.is_alive()))
+            }
+            VictoryCondition::Protect { team, target_id } => {
+                let safe = self.get_participant(target_id).is_some_and(Character::is_alive);
+                (!safe).then(|| {
+                    sides(&|c| self.teams.relation(team, self.teams.side(&c.id)) == Relation::Hostile)
+                })
+            }
+            VictoryCondition::SurviveRounds { team, rounds } => {
+                let survived = self.round() > *rounds
+                    && living.iter().any(|c| self.team(&c.id) == Some(team.as_str()));
+                survived.then(|| vec![team.clone()])
+            }
+            VictoryCondition::ReachHex { team, hex } => {
+                let reached = living
+                    .iter()
+                    .any(|c| c.position == *hex && self.team(&c.id) == Some(team.as_str()));
+                reached.then(|| vec![team.clone()])
+            }
+        }
+    }
+
+    pub fn turn_order(&self) -> &[InitiativeEntry] {
+        self.turn_order.entries()
+    }
+
+    pub fn round(&self) -> u32 {
+        self.turn_order.round()
+    }
+
+    /// The participant whose turn it is, if the encounter has started
+    pub fn current(&self) -> Option<&Character> {
+        self.get_participant(self.turn_order.current_id()?)
+    }
+
+    /// Start the next living participant's turn in initiative order
+    ///
+    /// End-of-turn effects of the outgoing participant and start-of-turn
+    /// effects of the incoming one are resolved and logged.
     pub fn next_turn(&mut self) -> Option<&Character> {
-        if self.participants.is_empty() {
+        let checkpoint = self.begin_action();
+        let id = self.advance_turn();
+        self.end_action(checkpoint, id.is_some());
+        self.get_participant(&id?)
+    }
+
+    /// Hand the turn on, returning the id of whoever now has it
+    fn advance_turn(&mut self) -> Option<String> {
+        self.check_victory();
+        if self.is_over() {
             return None;
         }
 
-        // Reset movement for the next character
-        if let Some(character) = self.participants.get_mut(self.current_turn) {
-            character.reset_movement();
+        if let Some(id) = self.turn_order.current_id().map(str::to_string) {
+            if let Some(character) = self.participants.get_mut(&id) {
+                let was_alive = character.is_alive();
+                let ticks = character.end_turn_effects(self.roller.as_mut());
+                let died = was_alive && !character.is_alive();
+                self.record_effect_ticks(&id, ticks);
+                if died {
+                    self.record(CombatEvent::Died { id });
+                }
+            }
+        }
+
+        // Start-of-turn damage can drop someone before they act; move on if so
+        for _ in 0..=self.participants.len() {
+            let participants = &self.participants;
+            let id = self
+                .turn_order
+                .advance(|id| participants.get(id).is_some_and(Character::is_alive))?
+                .to_string();
+
+            // Deaths at the end of the last turn or a new round can settle the encounter
+            self.check_victory();
+            if self.is_over() {
+                return None;
+            }
+
+            self.record(CombatEvent::TurnStarted { id: id.clone() });
+            self.reactions_used.remove(&id);
+
+            let character = self.participants.get_mut(&id)?;
+            let ticks = character.start_turn_effects(self.roller.as_mut());
+            let alive = character.is_alive();
+            if alive {
+                // Reset movement and actions for the character starting their turn
+                character.reset_movement();
+                self.budget = Some(TurnBudget::new(character));
+            }
+
+            self.record_effect_ticks(&id, ticks);
+            if alive {
+                return Some(id);
+            }
+            self.record(CombatEvent::Died { id });
         }
 
-        self.current_turn = (self.current_turn + 1) % self.participants.len();
-        self.participants.get(self.current_turn)
+        None
     }
 
-    pub fn attack(&mut self, attacker_idx: usize, defender_idx: usize) -> Option<AttackResult> {
-        let (attacker, defender) = match self.get_two_mut(attacker_idx, defender_idx) {
-            Some(pair) => pair,
-            None => return None,
-        };
+    /// Postpone the current participant's turn until after `after_id` acts
+    ///
+    /// Only possible before they have moved or spent any of their turn, since
+    /// the delayed turn starts afresh.
+    pub fn delay_turn(&mut self, after_id: &str) -> Result<Option<&Character>, CombatError> {
+        let next = self.undoable(|combat| -> Result<_, CombatError> {
+            let id = combat.turn_order.current_id().map(str::to_string);
+            if let Some(id) = &id {
+                if combat.budget.as_ref().is_some_and(|b| !b.is_untouched()) || combat.moved_this_turn(id) {
+                    return Err(CombatError::AlreadyActed);
+                }
+            }
+            combat.turn_order.delay(after_id)?;
+            if let Some(id) = id {
+                combat.record(CombatEvent::TurnDelayed { id, after_id: after_id.to_string() });
+            }
+            Ok(combat.advance_turn())
+        })?;
+        Ok(next.and_then(|id| self.get_participant(&id)))
+    }
+
+    /// Whether `id` has walked since their turn started
+    fn moved_this_turn(&self, id: &str) -> bool {
+        self.log
+            .entries()
+            .iter()
+            .rev()
+            .take_while(|e| !matches!(&e.event, CombatEvent::TurnStarted { id: started } if started == id))
+            .any(|e| matches!(&e.event, CombatEvent::Moved { id: mover, .. } if mover == id))
+    }
+
+    /// End the current turn holding a readied action
+    pub fn ready_action(&mut self) -> Result<Option<&Character>, CombatError> {
+        let next = self.undoable(|combat| -> Result<_, CombatError> {
+            combat.turn_order.ready()?;
+            if let Some(id) = combat.turn_order.current_id().map(str::to_string) {
+                combat.record(CombatEvent::ActionReadied { id });
+            }
+            Ok(combat.advance_turn())
+        })?;
+        Ok(next.and_then(|id| self.get_participant(&id)))
+    }
+
+    /// Spend a participant's readied action out of turn order
+    ///
+    /// Like any reaction this needs the participant's reaction to be unspent.
+    pub fn trigger_readied(&mut self, id: &str) -> Result<(), CombatError> {
+        self.undoable(|combat| {
+            if !combat.has_reaction(id) {
+                return Err(ActionError::Spent(ActionKind::Reaction).into());
+            }
+            if !combat.turn_order.take_readied(id) {
+                return Err(CombatError::NoReadiedAction);
+            }
+            combat.reactions_used.insert(id.to_string());
+            Ok(())
+        })
+    }
+
+    pub fn is_readied(&self, id: &str) -> bool {
+        self.turn_order.is_readied(id)
+    }
+
+    /// What the participant taking their turn has left to spend
+    pub fn budget(&self) -> Option<&TurnBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Whether a participant can take this kind of action right now
+    ///
+    /// Reactions are available any time once per round; everything else only
+    /// on the participant's own turn.
+    pub fn can_take(&self, id: &str, kind: ActionKind) -> bool {
+        match kind {
+            ActionKind::Reaction => self.get_participant(id).is_some() && self.has_reaction(id),
+            _ => self.budget.as_ref().is_some_and(|b| b.id == id && b.has(kind)),
+        }
+    }
+
+    /// Whether a participant can make another main-hand attack this turn
+    pub fn can_attack(&self, id: &str) -> bool {
+        self.budget.as_ref().is_some_and(|b| b.id == id && b.can_attack())
+    }
+
+    /// Spend part of a participant's turn on something `Combat` does not model
+    /// itself, such as dodging or a class feature
+    pub fn take_action(&mut self, id: &str, kind: ActionKind) -> Result<(), CombatError> {
+        if !self.participants.contains(id) {
+            return Err(CombatError::ParticipantNotFound);
+        }
+        self.undoable(|combat| {
+            if kind == ActionKind::Reaction {
+                if !combat.has_reaction(id) {
+                    return Err(ActionError::Spent(kind).into());
+                }
+                combat.reactions_used.insert(id.to_string());
+                return Ok(());
+            }
+            Ok(combat.turn_budget(id)?.spend(kind)?)
+        })
+    }
+
+    /// The budget of `id`, provided it is their turn
+    fn turn_budget(&mut self, id: &str) -> Result<&mut TurnBudget, ActionError> {
+        self.budget.as_mut().filter(|b| b.id == id).ok_or(ActionError::NotYourTurn)
+    }
+
+    /// Decide how a participant reacts; without one they take opportunity attacks only
+    pub fn set_reactor(&mut self, id: &str, reactor: Box<dyn Reactor>) {
+        self.reactors.insert(id.to_string(), reactor);
+    }
+
+    /// Whether a participant still has their reaction this round
+    pub fn has_reaction(&self, id: &str) -> bool {
+        !self.reactions_used.contains(id)
+    }
+
+    /// Offer everyone eligible a reaction to `trigger`, in initiative order
+    ///
+    /// Opportunity attacks are made immediately; the other reactions are
+    /// returned for the triggering action to apply before it completes.
+    pub fn trigger_reactions(&mut self, trigger: &ReactionTrigger) -> Vec<(String, Reaction)> {
+        let mut reactions = Vec::new();
+        for reactor_id in self.eligible_reactors(trigger) {
+            // Earlier reactions may have changed who can still react
+            if !self.get_participant(&reactor_id).is_some_and(Character::can_act) || self.is_over() {
+                continue;
+            }
+
+            let mut reactor = self.reactors.remove(&reactor_id);
+            let response = match reactor.as_mut() {
+                Some(reactor) => reactor.react(self, &reactor_id, trigger),
+                None => OpportunityAttacks.react(self, &reactor_id, trigger),
+            };
+            if let Some(reactor) = reactor {
+                self.reactors.insert(reactor_id.clone(), reactor);
+            }
+            let Some(reaction) = response.filter(|r| trigger.allows(r)) else {
+                continue;
+            };
+
+            self.reactions_used.insert(reactor_id.clone());
+            self.record(CombatEvent::Reacted { id: reactor_id.clone(), reaction });
+            if let (Reaction::OpportunityAttack, ReactionTrigger::LeavingReach { mover_id, .. }) = (reaction, trigger) {
+                // A failed attack (e.g. the mover already fell) simply wastes the reaction
+                let _ = self.resolve_attack(&reactor_id, mover_id, EquipmentSlot::MainHand, RollMode::Normal, false);
+            } else {
+                reactions.push((reactor_id, reaction));
+            }
+        }
+        reactions
+    }
+
+    fn eligible_reactors(&self, trigger: &ReactionTrigger) -> Vec<String> {
+        let ready = |c: &&Character| c.can_act() && self.has_reaction(&c.id);
+        let in_order = self
+            .turn_order
+            .entries()
+            .iter()
+            .filter_map(|e| self.get_participant(&e.participant_id))
+            .filter(ready);
+
+        match trigger {
+            ReactionTrigger::LeavingReach { mover_id, from, to } => {
+                let Some(grid) = &self.grid else {
+                    return Vec::new();
+                };
+                in_order
+                    .filter(|c| self.is_hostile(&c.id, mover_id))
+                    .filter(|c| !c.weapon(EquipmentSlot::MainHand).is_some_and(|w| w.is_ranged()))
+                    .filter(|c| {
+                        let threatened = grid.get_neighbors(c.position);
+                        threatened.contains(from) && !threatened.contains(to)
+                    })
+                    .map(|c| c.id.clone())
+                    .collect()
+            }
+            ReactionTrigger::Attacked { defender_id, .. } => {
+                in_order.filter(|c| c.id == *defender_id).map(|c| c.id.clone()).collect()
+            }
+            ReactionTrigger::SpellCast { caster_id, .. } => {
+                in_order.filter(|c| self.is_hostile(&c.id, caster_id)).map(|c| c.id.clone()).collect()
+            }
+        }
+    }
+
+    /// Hex occupied by a living participant other than `except_id`
+    pub fn is_occupied(&self, position: &HexPosition, except_id: &str) -> bool {
+        self.participants
+            .iter()
+            .any(|c| c.id != except_id && c.is_alive() && c.position == *position)
+    }
+
+    /// Move a participant along the cheapest grid path, spending their movement
+    ///
+    /// Only the participant taking their turn can move.
+    /// Stepping out of an enemy's reach provokes an opportunity attack before the
+    /// step is taken; if it drops or incapacitates the mover they stop where they
+    /// are. Returns the path actually walked.
+    pub fn move_participant(&mut self, id: &str, goal: HexPosition) -> Result<Vec<HexPosition>, CombatError> {
+        self.undoable(|combat| combat.walk(id, goal))
+    }
+
+    fn walk(&mut self, id: &str, goal: HexPosition) -> Result<Vec<HexPosition>, CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        let grid = self.grid.as_ref().ok_or(CombatError::NoGrid)?;
+        let mover = self.get_participant(id).ok_or(CombatError::ParticipantNotFound)?;
+        if !mover.is_alive() {
+            return Err(CombatError::AttackerDown);
+        }
+        if !mover.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
+        if self.budget.as_ref().is_none_or(|b| b.id != id) {
+            return Err(ActionError::NotYourTurn.into());
+        }
+        if self.is_occupied(&goal, id) {
+            return Err(CombatError::HexOccupied(goal));
+        }
+
+        let terrain_costs = mover.terrain_costs();
+        let path = grid
+            .find_path_avoiding(mover.position, goal, &terrain_costs, |hex| self.is_occupied(hex, id))
+            .ok_or(CombatError::NoPath)?;
+        let step_costs = path
+            .windows(2)
+            .map(|step| grid.path_cost_with(step, &terrain_costs))
+            .collect::<Option<Vec<i32>>>()
+            .ok_or(CombatError::NoPath)?;
+        let cost: i32 = step_costs.iter().sum();
+        if cost > mover.movement.remaining {
+            return Err(CombatError::NotEnoughMovement {
+                required: cost,
+                remaining: mover.movement.remaining,
+            });
+        }
 
-        // Basic attack roll (d20 + strength modifier)
-        let attack_roll = dice::roll(1, 20, (attacker.stats.strength - 10) / 2);
-        let defense = 10 + (defender.stats.dexterity - 10) / 2;
+        let mut walked = vec![path[0]];
+        let mut segment = (vec![path[0]], 0);
+        for (step, step_cost) in path.windows(2).zip(step_costs) {
+            let trigger = ReactionTrigger::LeavingReach {
+                mover_id: id.to_string(),
+                from: step[0],
+                to: step[1],
+            };
+            if !self.eligible_reactors(&trigger).is_empty() {
+                // Log the ground covered so far so the attack lands where it happened
+                self.record_move(id, std::mem::replace(&mut segment, (vec![step[0]], 0)));
+                self.trigger_reactions(&trigger);
+                if !self.get_participant(id).is_some_and(Character::can_act) || self.is_over() {
+                    break;
+                }
+            }
+
+            let mover = self.participants.get_mut(id).ok_or(CombatError::ParticipantNotFound)?;
+            mover.movement.remaining -= step_cost;
+            mover.position = step[1];
+            walked.push(step[1]);
+            segment.0.push(step[1]);
+            segment.1 += step_cost;
+        }
+        self.record_move(id, segment);
+
+        self.check_victory();
+        Ok(walked)
+    }
+
+    fn record_move(&mut self, id: &str, (path, cost): (Vec<HexPosition>, i32)) {
+        if path.len() > 1 {
+            self.record(CombatEvent::Moved { id: id.to_string(), path, cost });
+        }
+    }
+
+    /// Use a consumable from a participant's inventory, such as a health or mana potion,
+    /// returning the hit points or mana restored
+    ///
+    /// Takes the participant's action.
+    pub fn use_item(&mut self, id: &str, item_id: &str) -> Result<i32, CombatError> {
+        self.undoable(|combat| combat.consume_item(id, item_id))
+    }
+
+    fn consume_item(&mut self, id: &str, item_id: &str) -> Result<i32, CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        let character = self.participants.get_mut(id).ok_or(CombatError::ParticipantNotFound)?;
+        if !character.is_alive() {
+            return Err(CombatError::AttackerDown);
+        }
+        if !character.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
+        let budget = self.budget.as_mut().filter(|b| b.id == id).ok_or(ActionError::NotYourTurn)?;
+        if !budget.has(ActionKind::Action) {
+            return Err(ActionError::Spent(ActionKind::Action).into());
+        }
 
+        let (item, heals) = character
+            .inventory
+            .iter()
+            .find(|item| item.id == item_id)
+            .map(|item| {
+                let heals = matches!(&item.item_type, ItemType::Consumable(c) if c.healing().is_some());
+                (item.name.clone(), heals)
+            })
+            .ok_or(InventoryError::NotInInventory)?;
+        let restored = character.use_consumable(item_id, self.roller.as_mut())?;
+        budget.spend(ActionKind::Action)?;
+
+        let (healed, mana) = if heals { (restored, 0) } else { (0, restored) };
+        self.record(CombatEvent::ItemUsed { id: id.to_string(), item, healed, mana });
+        Ok(restored)
+    }
+
+    /// Let an AI play out the current participant's turn, returning the actions it took
+    ///
+    /// The turn is not ended; call `next_turn` afterwards as usual. `undo`
+    /// takes back everything the AI did at once. An action the rules refuse
+    /// stops the AI there: the failure is logged rather than returned, so a
+    /// loop driving AIs keeps moving.
+    ///
+    /// Fails only when no one is taking a turn.
+    pub fn take_ai_turn(&mut self, ai: &mut dyn CombatAi) -> Result<Vec<AiAction>, CombatError> {
+        self.undoable(|combat| combat.play_ai_turn(ai))
+    }
+
+    fn play_ai_turn(&mut self, ai: &mut dyn CombatAi) -> Result<Vec<AiAction>, CombatError> {
+        let id = self.turn_order.current_id().ok_or(CombatError::NoActiveTurn)?.to_string();
+        let mut taken = Vec::new();
+
+        while taken.len() < MAX_AI_ACTIONS && !self.is_over() {
+            if !self.get_participant(&id).is_some_and(Character::can_act) {
+                break;
+            }
+            let action = ai.next_action(self, &id, &taken);
+            let result = match &action {
+                AiAction::EndTurn => break,
+                AiAction::Move(goal) => self.move_participant(&id, *goal).map(drop),
+                AiAction::Attack { target_id } => self.attack(&id, target_id).map(drop),
+                AiAction::UseItem { item_id } => self.use_item(&id, item_id).map(drop),
+            };
+            if let Err(error) = result {
+                self.record(CombatEvent::AiActionFailed {
+                    id: id.clone(),
+                    action,
+                    reason: error.to_string(),
+                });
+                break;
+            }
+            taken.push(action);
+        }
+
+        Ok(taken)
+    }
+
+    /// Attack with the main-hand weapon (or fists)
+    ///
+    /// The first attack of a turn takes the participant's action, which allows
+    /// `Character::attacks_per_action` attacks in all.
+    pub fn attack(&mut self, attacker_id: &str, defender_id: &str) -> Result<AttackResult, CombatError> {
+        self.attack_with_mode(attacker_id, defender_id, RollMode::Normal)
+    }
+
+    pub fn attack_with_mode(&mut self, attacker_id: &str, defender_id: &str, mode: RollMode) -> Result<AttackResult, CombatError> {
+        self.undoable(|combat| combat.resolve_attack(attacker_id, defender_id, EquipmentSlot::MainHand, mode, true))
+    }
+
+    /// Attack with the weapon held in the off hand when dual wielding, as a bonus action
+    pub fn off_hand_attack(&mut self, attacker_id: &str, defender_id: &str, mode: RollMode) -> Result<AttackResult, CombatError> {
+        self.undoable(|combat| combat.resolve_attack(attacker_id, defender_id, EquipmentSlot::OffHand, mode, true))
+    }
+
+    /// Equip an item mid-fight, such as drawing a weapon, as a free interaction
+    pub fn equip_item(&mut self, id: &str, item_id: &str) -> Result<(), CombatError> {
+        self.undoable(|combat| combat.draw_item(id, item_id))
+    }
+
+    fn draw_item(&mut self, id: &str, item_id: &str) -> Result<(), CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        let character = self.participants.get_mut(id).ok_or(CombatError::ParticipantNotFound)?;
+        if !character.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
+        let budget = self.budget.as_mut().filter(|b| b.id == id).ok_or(ActionError::NotYourTurn)?;
+        if !budget.has(ActionKind::FreeInteraction) {
+            return Err(ActionError::Spent(ActionKind::FreeInteraction).into());
+        }
+        character.equip_item(item_id).map_err(CombatError::CannotUseItem)?;
+        Ok(budget.spend(ActionKind::FreeInteraction)?)
+    }
+
+    /// Resolve one attack; `budgeted` attacks are paid for from the attacker's turn,
+    /// the others (opportunity attacks) from elsewhere
+    fn resolve_attack(
+        &mut self,
+        attacker_id: &str,
+        defender_id: &str,
+        slot: EquipmentSlot,
+        mode: RollMode,
+        budgeted: bool,
+    ) -> Result<AttackResult, CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        if attacker_id == defender_id {
+            return Err(CombatError::SameParticipant);
+        }
+        if self.friendly_fire == FriendlyFire::Prevented && self.is_allied(attacker_id, defender_id) {
+            return Err(CombatError::FriendlyFire);
+        }
+        let (attacker, defender) = self
+            .participants
+            .get_pair_mut(attacker_id, defender_id)
+            .ok_or(CombatError::ParticipantNotFound)?;
+
+        if !attacker.is_alive() {
+            return Err(CombatError::AttackerDown);
+        }
+        if !attacker.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
         if !defender.is_alive() {
-            return None;
+            return Err(CombatError::TargetDown);
         }
 
-        let critical = attack_roll.value == 20;
-        let hit = critical || attack_roll.value >= defense;
+        let weapon = attacker.weapon(slot).cloned();
+        if slot == EquipmentSlot::OffHand && weapon.is_none() {
+            return Err(CombatError::NoOffHandWeapon);
+        }
 
-        if hit {
-            // Damage roll (1d6 + strength modifier)
-            let mut damage = dice::roll(1, 6, (attacker.stats.strength - 10) / 2).value;
+        // Melee needs an adjacent target; ranged weapons reach further
+        let distance = attacker.position.distance(&defender.position);
+        let range = attacker.weapon_range(slot);
+        if distance > range {
+            return Err(CombatError::OutOfRange { distance, range });
+        }
+
+        if budgeted {
+            let budget = self
+                .budget
+                .as_mut()
+                .filter(|b| b.id == attacker.id)
+                .ok_or(ActionError::NotYourTurn)?;
+            match slot {
+                EquipmentSlot::OffHand => budget.spend(ActionKind::BonusAction)?,
+                _ => budget.spend_attack(attacker.attacks_per_action())?,
+            }
+        }
+
+        let modifier = attacker.weapon_ability_modifier(weapon.as_ref());
+        let proficiency = attacker.weapon_proficiency_bonus(slot);
+        let damage_type = weapon.as_ref().map_or(UNARMED.damage_type, |w| w.damage_type);
+        let AttackRoll { roll: attack_roll, armor_class: defense, hit, critical, reduction } =
+            self.roll_attack(attacker_id, defender_id, modifier + proficiency, mode)?;
+        let roller = self.roller.as_mut();
+        let defender = self.participants.get_mut(defender_id).ok_or(CombatError::ParticipantNotFound)?;
+
+        let result = if hit {
+            // Off-hand attacks only add the ability modifier when it is a penalty
+            let damage_modifier = if slot == EquipmentSlot::OffHand { modifier.min(0) } else { modifier };
+            let damage_roll = roll_weapon_damage(roller, weapon.as_ref().unwrap_or(&UNARMED), damage_modifier);
+            let mut raw_damage = damage_roll.value.max(0);
             if critical {
-                damage *= 2;
+                raw_damage *= 2;
             }
 
-            defender.health.current -= damage;
+            let damage = defender.take_damage((raw_damage - reduction).max(0), damage_type);
 
-            Some(AttackResult {
+            AttackResult {
                 hit: true,
                 damage,
+                raw_damage,
+                mitigated: raw_damage - damage,
                 critical,
-            })
+                damage_type,
+                attack_roll,
+                damage_roll: Some(damage_roll),
+            }
         } else {
-            Some(AttackResult {
+            AttackResult {
                 hit: false,
                 damage: 0,
+                raw_damage: 0,
+                mitigated: 0,
                 critical: false,
-            })
+                damage_type,
+                attack_roll,
+                damage_roll: None,
+            }
+        };
+
+        let killed = result.hit && !defender.is_alive();
+        self.record(CombatEvent::Attacked {
+            attacker_id: attacker_id.to_string(),
+            defender_id: defender_id.to_string(),
+            attack_roll: result.attack_roll.clone(),
+            armor_class: defense,
+            hit: result.hit,
+            critical: result.critical,
+            damage_roll: result.damage_roll.clone(),
+            raw_damage: result.raw_damage,
+            damage: result.damage,
+            damage_type,
+        });
+        if killed {
+            self.record(CombatEvent::Died { id: defender_id.to_string() });
         }
+        self.maintain_concentration(defender_id, result.damage);
+        self.check_victory();
+
+        Ok(result)
     }
 
-    fn get_two_mut(&mut self, i: usize, j: usize) -> Option<(&mut Character, &mut Character)> {
-        if i == j || i >= self.participants.len() || j >= self.participants.len() {
-            return None;
+    /// Roll a d20 attack plus `bonus` and any effect dice such as bless, then let
+    /// the defender react to a hit before damage is rolled
+    ///
+    /// Weapon attacks and spell attacks both go through here.
+    fn roll_attack(
+        &mut self,
+        attacker_id: &str,
+        defender_id: &str,
+        bonus: i32,
+        mode: RollMode,
+    ) -> Result<AttackRoll, CombatError> {
+        let roller = self.roller.as_mut();
+        let attacker = self.participants.get(attacker_id).ok_or(CombatError::ParticipantNotFound)?;
+        let defender = self.participants.get(defender_id).ok_or(CombatError::ParticipantNotFound)?;
+        let mode = RollMode::from_sources([mode, attacker.attack_mode(), defender.incoming_attack_mode()]);
+        let options = RollOptions { mode, ..Default::default() };
+        let mut roll = dice::roll_with(roller, 1, 20, bonus, options);
+
+        // Bonus dice from effects such as bless
+        for effect in &attacker.effects {
+            if let Some(bonus) = &effect.attack_bonus {
+                let extra = bonus.evaluate(roller).total;
+                roll.value += extra;
+                roll.modifier += extra;
+            }
         }
+        let mut armor_class = defender.armor_class();
+
+        // A natural 20 always hits and crits; a natural 1 always misses
+        let critical = roll.natural_max;
+        let mut hit = critical || (!roll.natural_min && roll.value >= armor_class);
 
-        // Safe because we checked the indices are different and in bounds
-        unsafe {
-            let ptr = self.participants.as_mut_ptr();
-            Some((
-                &mut *ptr.add(i),
-                &mut *ptr.add(j),
-            ))
+        // The defender may react once the roll is known, before any damage lands
+        let mut reduction = 0;
+        if hit {
+            let trigger = ReactionTrigger::Attacked {
+                attacker_id: attacker_id.to_string(),
+                defender_id: defender_id.to_string(),
+                attack_total: roll.value,
+                armor_class,
+                critical,
+            };
+            for (_, reaction) in self.trigger_reactions(&trigger) {
+                match reaction {
+                    Reaction::ArmorBonus(bonus) => {
+                        armor_class += bonus;
+                        hit = critical || roll.value >= armor_class;
+                    }
+                    Reaction::ReduceDamage(amount) => reduction += amount,
+                    Reaction::OpportunityAttack | Reaction::Counter => {}
+                }
+            }
         }
+        Ok(AttackRoll { roll, armor_class, hit, critical, reduction })
     }
 }
 
@@ -99,10 +1074,17 @@ mod tests {
     use super::*;
     use crate::character::CharacterStats;
     use crate::item::RaceType;
+    use crate::grid::TerrainType;
+    use test_support::{character_at, open_grid};
+
+    fn participant_ids(combat: &Combat) -> Vec<String> {
+        combat.roster().ids().map(str::to_string).collect()
+    }
 
     #[test]
     fn test_combat_turn_order() {
-        let mut combat = Combat::new();
+        // Initiative rolls: Fighter 1 rolls 8, Fighter 2 rolls 15
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([8, 15])));
         
         let stats = CharacterStats {
             strength: 10,
@@ -118,5 +1100,376 @@ mod tests {
 
         let next = combat.next_turn().unwrap();
         assert_eq!(next.name, "Fighter 2");
+        let next = combat.next_turn().unwrap();
+        assert_eq!(next.name, "Fighter 1");
+        assert_eq!(combat.round(), 1);
+
+        // Dead participants are skipped and wrapping starts a new round
+        let fighter_2 = combat.turn_order()[0].participant_id.clone();
+        combat.participants.iter_mut().find(|c| c.id == fighter_2).unwrap().health.current = 0;
+        let next = combat.next_turn().unwrap();
+        assert_eq!(next.name, "Fighter 1");
+        assert_eq!(combat.round(), 2);
+    }
+
+    #[test]
+    fn test_adding_twice_replaces() {
+        let mut combat = Combat::new();
+        let stats = CharacterStats::from_scores([10; 6]);
+        let mut hero = Character::new("Hero".to_string(), RaceType::Human, stats.clone());
+        combat.add_participant_with_initiative(hero.clone(), 12);
+        combat.add_participant_with_initiative(Character::new("Foe".to_string(), RaceType::Orc, stats), 8);
+        hero.health.current = 5;
+        combat.add_participant_with_initiative(hero.clone(), 15);
+
+        assert_eq!(combat.turn_order().len(), 2);
+        assert_eq!(combat.turn_order()[0].total, 15);
+        assert_eq!(combat.get_participant(&hero.id).unwrap().health.current, 5);
+        combat.next_turn();
+        combat.next_turn();
+        assert_eq!(combat.next_turn().unwrap().id, hero.id);
+        assert_eq!(combat.round(), 2);
+    }
+
+    #[test]
+    fn test_cannot_delay_after_acting() {
+        let mut grid = HexGrid::new();
+        for q in 0..5 {
+            grid.add_cell(HexPosition::new_2d(q, 0), TerrainType::Plain, 0);
+        }
+        // Only the attacker's miss is rolled
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([2])));
+        combat.set_grid(grid);
+        let stats = CharacterStats::from_scores([10; 6]);
+        for (name, q, initiative) in [("Attacker", 0, 20), ("Scout", 4, 15), ("Target", 1, 10)] {
+            let mut character = Character::new(name.to_string(), RaceType::Human, stats.clone());
+            character.position = HexPosition::new_2d(q, 0);
+            combat.add_participant_with_initiative(character, initiative);
+        }
+        let ids: Vec<String> = combat.turn_order().iter().map(|e| e.participant_id.clone()).collect();
+
+        // Delaying would hand the attacker a second, fresh turn this round
+        combat.next_turn();
+        combat.attack(&ids[0], &ids[2]).unwrap();
+        assert_eq!(combat.delay_turn(&ids[2]).unwrap_err(), CombatError::AlreadyActed);
+        assert_eq!(combat.current().unwrap().id, ids[0]);
+        assert_eq!(combat.budget().unwrap().actions, 0);
+
+        combat.next_turn();
+        combat.move_participant(&ids[1], HexPosition::new_2d(3, 0)).unwrap();
+        assert_eq!(combat.delay_turn(&ids[2]).unwrap_err(), CombatError::AlreadyActed);
+        assert_eq!(combat.current().unwrap().movement.remaining, 5);
+    }
+
+    #[test]
+    fn test_delay_and_ready_errors() {
+        let mut combat = Combat::new();
+        let stats = CharacterStats::from_scores([10; 6]);
+        for (name, initiative) in [("First", 20), ("Second", 10)] {
+            let character = Character::new(name.to_string(), RaceType::Human, stats.clone());
+            combat.add_participant_with_initiative(character, initiative);
+        }
+        let ids: Vec<String> = combat.turn_order().iter().map(|e| e.participant_id.clone()).collect();
+
+        assert_eq!(combat.delay_turn(&ids[1]).unwrap_err(), CombatError::NoActiveTurn);
+        combat.next_turn();
+        assert_eq!(combat.delay_turn(&ids[0]).unwrap_err(), CombatError::CannotDelayPastSelf);
+        assert_eq!(combat.delay_turn("nobody").unwrap_err(), CombatError::NotInTurnOrder);
+
+        assert_eq!(combat.trigger_readied(&ids[0]), Err(CombatError::NoReadiedAction));
+        combat.ready_action().unwrap();
+        assert_eq!(combat.trigger_readied(&ids[0]), Ok(()));
+        assert_eq!(
+            combat.trigger_readied(&ids[0]),
+            Err(CombatError::Action(ActionError::Spent(ActionKind::Reaction)))
+        );
+    }
+
+    #[test]
+    fn test_attack_with_scripted_roller() {
+        let roller = dice::ScriptedRoller::new([10, 10, 15, 4]);
+        let mut combat = Combat::with_roller(Box::new(roller));
+
+        let stats = CharacterStats::from_scores([10; 6]);
+
+        combat.add_participant(Character::new("Attacker".to_string(), RaceType::Human, stats.clone()));
+        combat.add_participant(Character::new("Defender".to_string(), RaceType::Orc, stats.clone()));
+        let ids = participant_ids(&combat);
+        combat.next_turn();
+
+        let result = combat.attack(&ids[0], &ids[1]).unwrap();
+        assert!(result.hit);
+        assert!(!result.critical);
+        assert_eq!(result.damage, 4);
+    }
+
+    #[test]
+    fn test_critical_uses_natural_roll() {
+        // A natural 19 with a +5 modifier is not a critical hit
+        let roller = dice::ScriptedRoller::new([10, 10, 19, 4]);
+        let mut combat = Combat::with_roller(Box::new(roller));
+
+        let strong = CharacterStats::from_scores([20, 10, 10, 10, 10, 10]);
+        let weak = CharacterStats::from_scores([10; 6]);
+
+        combat.add_participant(Character::new("Brute".to_string(), RaceType::Orc, strong));
+        combat.add_participant(Character::new("Target".to_string(), RaceType::Human, weak));
+        let ids = participant_ids(&combat);
+        combat.next_turn();
+
+        let result = combat.attack(&ids[0], &ids[1]).unwrap();
+        assert_eq!(result.attack_roll.value, 24);
+        assert!(result.hit);
+        assert!(!result.critical);
+        assert_eq!(result.damage, 9);
+    }
+
+    #[test]
+    fn test_grid_movement_and_reach() {
+        let mut grid = open_grid(5);
+        grid.add_cell(HexPosition::new_2d(2, 0), TerrainType::Rough, 0);
+
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([15, 10, 12, 3])));
+        combat.set_grid(grid);
+
+        let fighter = character_at("Fighter", 0, 0);
+        let mut goblin = Character::new("Goblin".to_string(), RaceType::Orc, CharacterStats::from_scores([10; 6]));
+        goblin.position = HexPosition::new_2d(3, 0);
+        let fighter_id = fighter.id.clone();
+        combat.add_participant(fighter);
+        combat.add_participant(goblin);
+        let ids = participant_ids(&combat);
+        combat.next_turn();
+
+        assert_eq!(
+            combat.attack(&ids[0], &ids[1]).unwrap_err(),
+            CombatError::OutOfRange { distance: 3, range: 1 }
+        );
+        assert_eq!(
+            combat.move_participant(&fighter_id, HexPosition::new_2d(3, 0)).unwrap_err(),
+            CombatError::HexOccupied(HexPosition::new_2d(3, 0))
+        );
+
+        let path = combat.move_participant(&fighter_id, HexPosition::new_2d(2, 1)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(combat.participants()[0].movement.remaining, 3);
+        assert!(combat.attack(&ids[0], &ids[1]).unwrap().hit);
+    }
+
+    #[test]
+    fn test_movement_paths_around_occupied_hexes() {
+        let corridor = |rows: i32| {
+            let mut grid = HexGrid::new();
+            for q in 0..5 {
+                for r in 0..rows {
+                    grid.add_cell(HexPosition::new_2d(q, r), TerrainType::Plain, 0);
+                }
+            }
+            grid
+        };
+        let setup = |grid: HexGrid, faces: Vec<i32>| {
+            let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new(faces)));
+            combat.set_grid(grid);
+            let fighter = character_at("Fighter", 0, 0);
+            let mut goblin = Character::new("Goblin".to_string(), RaceType::Orc, CharacterStats::from_scores([10; 6]));
+            goblin.position = HexPosition::new_2d(2, 0);
+            let fighter_id = fighter.id.clone();
+            combat.add_participant(fighter);
+            combat.add_participant(goblin);
+            combat.next_turn();
+            (combat, fighter_id)
+        };
+
+        // A goblin standing in a one-hex corridor cannot be walked through
+        let (mut combat, fighter_id) = setup(corridor(1), vec![15, 10]);
+        assert_eq!(
+            combat.move_participant(&fighter_id, HexPosition::new_2d(4, 0)).unwrap_err(),
+            CombatError::NoPath
+        );
+        assert_eq!(combat.participants()[0].position, HexPosition::new_2d(0, 0));
+
+        // With room to spare the fighter steps around it, drawing a missed opportunity attack
+        let (mut combat, fighter_id) = setup(corridor(2), vec![15, 10, 1]);
+        let path = combat.move_participant(&fighter_id, HexPosition::new_2d(4, 0)).unwrap();
+        assert_eq!(path.len(), 6);
+        assert!(!path.contains(&HexPosition::new_2d(2, 0)));
+        assert_eq!(combat.participants()[0].position, HexPosition::new_2d(4, 0));
+    }
+
+    #[test]
+    fn test_equipment_driven_attacks() {
+        use crate::item::{EquipmentType, Item, ItemStats, WeaponType};
+
+        let weapon_stats = |min_damage, max_damage, finesse, armor| ItemStats {
+            strength_bonus: 0,
+            dexterity_bonus: 0,
+            constitution_bonus: 0,
+            intelligence_bonus: 0,
+            wisdom_bonus: 0,
+            charisma_bonus: 0,
+            armor,
+            damage: if max_damage > 0 {
+                Some(WeaponDamage {
+                    min_damage,
+                    max_damage,
+                    damage_type: DamageType::Piercing,
+                    range: None,
+                    finesse,
+                })
+            } else {
+                None
+            },
+            defenses: Default::default(),
+        };
+
+        let stats = CharacterStats::from_scores([8, 16, 10, 10, 10, 10]);
+        let mut rogue = Character::new("Rogue".to_string(), RaceType::Elf, stats.clone());
+        for item in [
+            Item::new_equipment("Rapier".to_string(), EquipmentType::Weapon(WeaponType::OneHanded), weapon_stats(1, 8, true, 0), None, 1),
+            Item::new_equipment("Dagger".to_string(), EquipmentType::Weapon(WeaponType::OneHanded), weapon_stats(1, 4, true, 0), None, 1),
+        ] {
+            let id = item.id.clone();
+            rogue.add_to_inventory(item).unwrap();
+            rogue.equip_item(&id).unwrap();
+        }
+
+        let mut knight = Character::new("Knight".to_string(), RaceType::Human, stats.clone());
+        let plate = Item::new_equipment("Plate".to_string(), EquipmentType::ChestPiece, weapon_stats(0, 0, false, 5), None, 1);
+        let plate_id = plate.id.clone();
+        knight.add_to_inventory(plate).unwrap();
+        knight.equip_item(&plate_id).unwrap();
+        // 10 + 3 (dexterity) + 5 (plate)
+        assert_eq!(knight.armor_class(), 18);
+
+        // Initiative, then the rapier (d20 15 + 3 dex) and its damage (5 + 3)
+        let roller = dice::ScriptedRoller::new([10, 10, 15, 5, 16, 3]);
+        let mut combat = Combat::with_roller(Box::new(roller));
+        combat.add_participant(rogue);
+        combat.add_participant(knight);
+        let ids = participant_ids(&combat);
+        combat.next_turn();
+
+        let result = combat.attack(&ids[0], &ids[1]).unwrap();
+        assert!(result.hit);
+        assert_eq!(result.attack_roll.value, 18);
+        assert_eq!(result.damage, 8);
+        assert_eq!(result.damage_type, DamageType::Piercing);
+
+        // The off-hand dagger hits but does not add the positive modifier
+        let result = combat.off_hand_attack(&ids[0], &ids[1], RollMode::Normal).unwrap();
+        assert!(result.hit);
+        assert_eq!(result.damage, 3);
+        assert!(combat.off_hand_attack(&ids[1], &ids[0], RollMode::Normal).is_err());
+    }
+
+    #[test]
+    fn test_damage_mitigation() {
+        use crate::item::DamageDefenses;
+
+        let stats = CharacterStats::from_scores([10; 6]);
+        let mut ghost = Character::new("Ghost".to_string(), RaceType::Human, stats.clone());
+        ghost.defenses = DamageDefenses::default();
+        ghost.defenses.resistances.insert(DamageType::Blunt);
+
+        // Initiative, then an unarmed hit for 5
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([10, 10, 15, 5])));
+        combat.add_participant(Character::new("Monk".to_string(), RaceType::Human, stats.clone()));
+        combat.add_participant(ghost);
+        let ids = participant_ids(&combat);
+        combat.next_turn();
+
+        let result = combat.attack(&ids[0], &ids[1]).unwrap();
+        assert_eq!(result.raw_damage, 5);
+        assert_eq!(result.damage, 2);
+        assert_eq!(result.mitigated, 3);
+        assert_eq!(combat.participants()[1].health.current, 18);
+    }
+
+    #[test]
+    fn test_effects_in_combat() {
+        use crate::effect::StatusEffect;
+
+        let stats = CharacterStats::from_scores([10; 6]);
+        let mut burning = Character::new("Burning".to_string(), RaceType::Human, stats.clone());
+        burning.add_effect(StatusEffect::burning(1));
+        let mut stunned = Character::new("Stunned".to_string(), RaceType::Human, stats.clone());
+        stunned.add_effect(StatusEffect::stunned(1));
+
+        // Initiative 15 and 10, then 1d4 burn, then advantage against the stunned target
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([15, 10, 3, 4, 12, 2])));
+        combat.add_participant(burning);
+        combat.add_participant(stunned);
+        let ids = participant_ids(&combat);
+
+        let current = combat.next_turn().unwrap();
+        assert_eq!(current.health.current, 17);
+        let ticks = combat.log().entries().iter().filter(|e| matches!(e.event, CombatEvent::EffectTicked { .. }));
+        assert_eq!(ticks.count(), 1);
+        assert_eq!(combat.attack(&ids[1], &ids[0]).unwrap_err(), CombatError::Incapacitated);
+
+        let result = combat.attack(&ids[0], &ids[1]).unwrap();
+        assert_eq!(result.attack_roll.mode, RollMode::Advantage);
+        assert_eq!(result.attack_roll.natural(), 12);
+
+        // Burning expires when its owner's turn ends
+        combat.next_turn().unwrap();
+        assert!(combat.log().entries().iter().any(|e| matches!(
+            e.event,
+            CombatEvent::EffectTicked { tick: EffectTick::Expired { .. }, .. }
+        )));
+    }
+
+    #[test]
+    fn test_teams_and_victory_conditions() {
+        let stats = CharacterStats::from_scores([10; 6]);
+        let guard = Character::new("Guard".to_string(), RaceType::Human, stats.clone());
+        let mut envoy = Character::new("Envoy".to_string(), RaceType::Elf, stats.clone());
+        envoy.health.current = 1;
+        let raider = Character::new("Raider".to_string(), RaceType::Orc, stats.clone());
+        let (guard_id, envoy_id, raider_id) = (guard.id.clone(), envoy.id.clone(), raider.id.clone());
+
+        // Initiative for three, then the raider's hit
+        let mut combat = Combat::with_roller(Box::new(dice::ScriptedRoller::new([10, 10, 10, 15, 4])));
+        combat.add_participant(guard);
+        combat.add_participant(envoy);
+        combat.add_participant(raider);
+        let ids = participant_ids(&combat);
+        combat.set_team(&guard_id, "escort");
+        combat.set_team(&envoy_id, "escort");
+        combat.set_team(&raider_id, "raiders");
+        combat.add_victory_condition(VictoryCondition::Protect {
+            team: "escort".to_string(),
+            target_id: envoy_id.clone(),
+        });
+
+        assert!(combat.is_hostile(&guard_id, &raider_id));
+        combat.next_turn();
+        assert_eq!(combat.attack(&ids[0], &ids[1]).unwrap_err(), CombatError::FriendlyFire);
+
+        // On to the raider's turn
+        combat.next_turn();
+        combat.next_turn();
+        combat.attack(&ids[2], &ids[1]).unwrap();
+        let outcome = combat.outcome().unwrap();
+        assert_eq!(outcome.winners, vec!["raiders".to_string()]);
+        assert!(combat.next_turn().is_none());
+        assert_eq!(combat.attack(&ids[0], &ids[2]).unwrap_err(), CombatError::EncounterOver);
+        assert!(matches!(combat.log().entries().last().unwrap().event, CombatEvent::Ended { .. }));
+
+        // Holding out: the defenders win once the first round is over
+        let mut combat = Combat::with_seed(3);
+        let defender = Character::new("Defender".to_string(), RaceType::Dwarf, stats.clone());
+        let attacker = Character::new("Attacker".to_string(), RaceType::Orc, stats);
+        combat.set_team(&defender.id, "keep");
+        combat.set_team(&attacker.id, "horde");
+        combat.add_participant(defender);
+        combat.add_participant(attacker);
+        combat.add_victory_condition(VictoryCondition::SurviveRounds { team: "keep".to_string(), rounds: 1 });
+
+        assert!(combat.next_turn().is_some());
+        assert!(combat.next_turn().is_some());
+        assert!(combat.next_turn().is_none());
+        assert_eq!(combat.outcome().unwrap().winners, vec!["keep".to_string()]);
+        assert_eq!(combat.outcome().unwrap().round, 2);
     }
 }
@@ -0,0 +1,449 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character, HexPosition,
+    character::MELEE_REACH,
+};
+use super::{ActionKind, Combat};
+
+/// One step of an AI-controlled turn
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiAction {
+    Move(HexPosition),
+    Attack { target_id: String },
+    UseItem { item_id: String },
+    EndTurn,
+}
+
+/// Decides what a non-player combatant does on its turn
+///
+/// `Combat::take_ai_turn` asks for one action at a time, applying each before
+/// asking again, until the AI ends the turn. An action that fails ends the
+/// turn early and is logged as `CombatEvent::AiActionFailed`. Implementations should be pure
+/// functions of the combat state so that a seeded `Combat` replays identically;
+/// one that wants randomness should carry its own seeded `DiceRoller`.
+pub trait CombatAi {
+    fn next_action(&mut self, combat: &Combat, actor_id: &str, taken: &[AiAction]) -> AiAction;
+}
+
+/// When a combatant stops fighting to look after itself
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tactics {
+    /// Drink a healing consumable at or below this fraction of maximum health
+    pub heal_below: f32,
+    /// Fall back from enemies at or below this fraction of maximum health
+    pub retreat_below: f32,
+}
+
+impl Default for Tactics {
+    fn default() -> Self {
+        Self {
+            heal_below: 0.5,
+            retreat_below: 0.25,
+        }
+    }
+}
+
+/// Closes to melee with the nearest enemy and hits the weakest one in reach
+#[derive(Debug, Clone, Default)]
+pub struct AggressiveMelee {
+    pub tactics: Tactics,
+}
+
+/// Keeps enemies at weapon range, backing off whenever one gets adjacent
+#[derive(Debug, Clone, Default)]
+pub struct KitingRanged {
+    pub tactics: Tactics,
+}
+
+/// Holds a post, only engaging enemies that come within `radius` of it
+#[derive(Debug, Clone)]
+pub struct DefensiveGuard {
+    pub post: HexPosition,
+    pub radius: i32,
+    pub tactics: Tactics,
+}
+
+impl AggressiveMelee {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KitingRanged {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl DefensiveGuard {
+    pub fn new(post: HexPosition, radius: i32) -> Self {
+        Self {
+            post,
+            radius,
+            tactics: Tactics::default(),
+        }
+    }
+}
+
+impl CombatAi for AggressiveMelee {
+    fn next_action(&mut self, combat: &Combat, actor_id: &str, taken: &[AiAction]) -> AiAction {
+        let Some(actor) = combat.get_participant(actor_id) else {
+            return AiAction::EndTurn;
+        };
+        let enemies = enemies(combat, actor);
+        if let Some(action) = look_after_self(combat, actor, &enemies, &self.tactics, taken) {
+            return action;
+        }
+
+        if combat.can_attack(actor_id) {
+            if let Some(target) = weakest_in_range(actor, &enemies, actor.attack_range()) {
+                return attack(target);
+            }
+        }
+        if !has_moved(taken) {
+            if let Some(target) = nearest(actor.position, &enemies) {
+                let goal = target.position;
+                if let Some(hex) = best_hex(combat, actor, |hex| -hex.distance(&goal)) {
+                    return AiAction::Move(hex);
+                }
+            }
+        }
+        AiAction::EndTurn
+    }
+}
+
+impl CombatAi for KitingRanged {
+    fn next_action(&mut self, combat: &Combat, actor_id: &str, taken: &[AiAction]) -> AiAction {
+        let Some(actor) = combat.get_participant(actor_id) else {
+            return AiAction::EndTurn;
+        };
+        let enemies = enemies(combat, actor);
+        if let Some(action) = look_after_self(combat, actor, &enemies, &self.tactics, taken) {
+            return action;
+        }
+
+        let range = actor.attack_range();
+        // Somewhere in range of an enemy but as far from all of them as possible
+        let kite = |hex: HexPosition| {
+            let closest = enemies.iter().map(|e| hex.distance(&e.position)).min().unwrap_or(0);
+            if closest <= range {
+                1000 + closest
+            } else {
+                -closest
+            }
+        };
+
+        let threatened = enemies.iter().any(|e| actor.position.distance(&e.position) <= MELEE_REACH);
+        if threatened && !has_moved(taken) {
+            if let Some(hex) = best_hex(combat, actor, kite) {
+                return AiAction::Move(hex);
+            }
+        }
+        if combat.can_attack(actor_id) {
+            if let Some(target) = weakest_in_range(actor, &enemies, range) {
+                return attack(target);
+            }
+        }
+        if !has_moved(taken) && !enemies.is_empty() {
+            if let Some(hex) = best_hex(combat, actor, kite) {
+                return AiAction::Move(hex);
+            }
+        }
+        AiAction::EndTurn
+    }
+}
+
+impl CombatAi for DefensiveGuard {
+    fn next_action(&mut self, combat: &Combat, actor_id: &str, taken: &[AiAction]) -> AiAction {
+        let Some(actor) = combat.get_participant(actor_id) else {
+            return AiAction::EndTurn;
+        };
+        let enemies = enemies(combat, actor);
+        if let Some(action) = look_after_self(combat, actor, &enemies, &self.tactics, taken) {
+            return action;
+        }
+
+        if combat.can_attack(actor_id) {
+            if let Some(target) = weakest_in_range(actor, &enemies, actor.attack_range()) {
+                return attack(target);
+            }
+        }
+        if !has_moved(taken) {
+            let (post, radius) = (self.post, self.radius);
+            let intruders: Vec<&Character> = enemies
+                .iter()
+                .copied()
+                .filter(|e| e.position.distance(&post) <= radius)
+                .collect();
+            // Chase intruders without leaving the guarded area, otherwise head back to the post
+            let goal = nearest(actor.position, &intruders).map_or(post, |e| e.position);
+            let hex = best_hex(combat, actor, |hex| {
+                if hex.distance(&post) > radius {
+                    -1000 - hex.distance(&post)
+                } else {
+                    -hex.distance(&goal)
+                }
+            });
+            if let Some(hex) = hex {
+                return AiAction::Move(hex);
+            }
+        }
+        AiAction::EndTurn
+    }
+}
+
+/// Heal or retreat when badly hurt; `None` to carry on fighting
+fn look_after_self(
+    combat: &Combat,
+    actor: &Character,
+    enemies: &[&Character],
+    tactics: &Tactics,
+    taken: &[AiAction],
+) -> Option<AiAction> {
+    let health = actor.health.current as f32 / actor.health.maximum.max(1) as f32;
+
+    if combat.can_take(&actor.id, ActionKind::Action) && health <= tactics.heal_below {
+        if let Some(item) = actor.healing_item() {
+            return Some(AiAction::UseItem { item_id: item.id.clone() });
+        }
+    }
+    if !has_moved(taken) && health <= tactics.retreat_below && !enemies.is_empty() {
+        let hex = best_hex(combat, actor, |hex| {
+            enemies.iter().map(|e| hex.distance(&e.position)).min().unwrap_or(0)
+        });
+        return hex.map(AiAction::Move);
+    }
+    None
+}
+
+/// Living participants the actor fights against
+fn enemies<'a>(combat: &'a Combat, actor: &Character) -> Vec<&'a Character> {
+    combat
+        .participants()
+        .iter()
+        .filter(|c| c.is_alive() && combat.is_hostile(&actor.id, &c.id))
+        .collect()
+}
+
+fn nearest<'a>(from: HexPosition, candidates: &[&'a Character]) -> Option<&'a Character> {
+    candidates
+        .iter()
+        .copied()
+        .min_by_key(|c| (from.distance(&c.position), c.health.current))
+}
+
+/// The enemy within `range` closest to death, to finish it off
+fn weakest_in_range<'a>(actor: &Character, enemies: &[&'a Character], range: i32) -> Option<&'a Character> {
+    enemies
+        .iter()
+        .copied()
+        .filter(|e| actor.position.distance(&e.position) <= range)
+        .min_by_key(|e| (e.health.current, actor.position.distance(&e.position)))
+}
+
+fn attack(target: &Character) -> AiAction {
+    AiAction::Attack { target_id: target.id.clone() }
+}
+
+fn has_moved(taken: &[AiAction]) -> bool {
+    taken.iter().any(|a| matches!(a, AiAction::Move(_)))
+}
+
+/// The reachable hex with the highest score, if it beats staying put
+///
+/// Ties go to the cheaper move, then to the lower coordinates, so the choice
+/// never depends on hash map ordering.
+fn best_hex(combat: &Combat, actor: &Character, score: impl Fn(HexPosition) -> i32) -> Option<HexPosition> {
+    let stay = score(actor.position);
+    let mut best: Option<(i32, HexPosition)> = None;
+    for (hex, _) in reachable(combat, actor) {
+        let value = score(hex);
+        if value > stay && best.is_none_or(|(b, _)| value > b) {
+            best = Some((value, hex));
+        }
+    }
+    best.map(|(_, hex)| hex)
+}
+
+/// Hexes the actor can walk to with its remaining movement, cheapest first
+///
+/// Occupied hexes are neither entered nor passed through.
+fn reachable(combat: &Combat, actor: &Character) -> Vec<(HexPosition, i32)> {
+    let Some(grid) = combat.grid() else {
+        return Vec::new();
+    };
+    let budget = actor.movement.remaining;
+    let terrain_costs = actor.terrain_costs();
+    let key = |p: HexPosition| (p.q, p.r, p.z);
+
+    let mut costs: HashMap<HexPosition, i32> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    costs.insert(actor.position, 0);
+    open.push(Reverse((0, key(actor.position))));
+
+    while let Some(Reverse((cost, (q, r, z)))) = open.pop() {
+        let position = HexPosition::new(q, r, z);
+        if costs.get(&position).is_some_and(|&c| c < cost) {
+            continue;
+        }
+        for neighbor in grid.get_neighbors(position) {
+            if combat.is_occupied(&neighbor, &actor.id) {
+                continue;
+            }
+            let Some(step) = grid.path_cost_with(&[position, neighbor], &terrain_costs) else {
+                continue;
+            };
+            let total = cost + step;
+            if total > budget || costs.get(&neighbor).is_some_and(|&c| c <= total) {
+                continue;
+            }
+            costs.insert(neighbor, total);
+            open.push(Reverse((total, key(neighbor))));
+        }
+    }
+
+    let mut hexes: Vec<(HexPosition, i32)> = costs.into_iter().filter(|(p, _)| *p != actor.position).collect();
+    hexes.sort_by_key(|(p, cost)| (*cost, key(*p)));
+    hexes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        combat::{CombatError, CombatEvent, test_support::{character_at, open_grid}},
+        dice::ScriptedRoller,
+        item::{ConsumableType, EquipmentSlot, Item},
+    };
+
+    #[test]
+    fn test_aggressive_melee_closes_and_attacks() {
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 5, 12, 3])));
+        combat.set_grid(open_grid(10));
+        combat.add_participant(character_at("Brute", 0, 0));
+        combat.add_participant(character_at("Victim", 4, 0));
+        let victim = combat.participants()[1].id.clone();
+
+        combat.next_turn();
+        let actions = combat.take_ai_turn(&mut AggressiveMelee::new()).unwrap();
+        assert_eq!(actions, vec![
+            AiAction::Move(HexPosition::new_2d(3, 0)),
+            AiAction::Attack { target_id: victim.clone() },
+        ]);
+        assert_eq!(combat.get_participant(&victim).unwrap().health.current, 17);
+    }
+
+    /// Always swings at the same target, however far away
+    struct Stubborn(String);
+
+    impl CombatAi for Stubborn {
+        fn next_action(&mut self, _combat: &Combat, _actor_id: &str, _taken: &[AiAction]) -> AiAction {
+            AiAction::Attack { target_id: self.0.clone() }
+        }
+    }
+
+    #[test]
+    fn test_failed_action_ends_ai_turn() {
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 5])));
+        combat.set_grid(open_grid(10));
+        combat.add_participant(character_at("Brute", 0, 0));
+        combat.add_participant(character_at("Victim", 4, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+
+        combat.next_turn();
+        let actions = combat.take_ai_turn(&mut Stubborn(ids[1].clone())).unwrap();
+        assert!(actions.is_empty());
+        assert_eq!(combat.log().entries().last().unwrap().event, CombatEvent::AiActionFailed {
+            id: ids[0].clone(),
+            action: AiAction::Attack { target_id: ids[1].clone() },
+            reason: CombatError::OutOfRange { distance: 4, range: 1 }.to_string(),
+        });
+
+        // The loop driving the encounter moves on to the next participant
+        assert_eq!(combat.next_turn().unwrap().id, ids[1]);
+    }
+
+    #[test]
+    fn test_low_health_drinks_potion_then_retreats() {
+        let mut hurt = character_at("Hurt", 5, 5);
+        hurt.health.current = 1;
+        let potion = Item::new_consumable("Potion".to_string(), ConsumableType::HealthPotion, String::new());
+        let potion_id = potion.id.clone();
+        hurt.add_to_inventory(potion).unwrap();
+
+        // Initiative, 2d4 for the potion, then the foe's opportunity attack misses
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 5, 1, 1, 2])));
+        combat.set_grid(open_grid(10));
+        combat.add_participant(hurt);
+        combat.add_participant(character_at("Foe", 5, 6));
+        let hurt_id = combat.participants()[0].id.clone();
+
+        combat.next_turn();
+        let actions = combat.take_ai_turn(&mut AggressiveMelee::new()).unwrap();
+        assert_eq!(actions[0], AiAction::UseItem { item_id: potion_id });
+        assert_eq!(actions.len(), 2);
+
+        // Still only on 5 of 20 health, so it falls back rather than fighting
+        let hurt = combat.get_participant(&hurt_id).unwrap();
+        assert_eq!(hurt.health.current, 5);
+        assert!(hurt.position.distance(&HexPosition::new_2d(5, 6)) > 1);
+    }
+
+    #[test]
+    fn test_kiting_and_determinism() {
+        let run = |seed| {
+            let mut combat = Combat::with_seed(seed);
+            combat.set_grid(open_grid(10));
+            let mut archer = character_at("Archer", 2, 2);
+            archer.equipment.insert(EquipmentSlot::MainHand, bow());
+            combat.add_participant(archer);
+            combat.add_participant(character_at("Rusher", 2, 3));
+            for _ in 0..6 {
+                let Some(current) = combat.next_turn().map(|c| c.name.clone()) else {
+                    break;
+                };
+                if current == "Archer" {
+                    let adjacent = combat.participants()[0].position.distance(&combat.participants()[1].position) <= 1;
+                    let actions = combat.take_ai_turn(&mut KitingRanged::new()).unwrap();
+                    if adjacent {
+                        // Backs off before shooting
+                        assert!(matches!(actions[0], AiAction::Move(_)));
+                    }
+                } else {
+                    combat.take_ai_turn(&mut AggressiveMelee::new()).unwrap();
+                }
+            }
+            combat.log().render()
+        };
+        assert_eq!(run(7), run(7));
+    }
+
+    fn bow() -> Item {
+        use crate::item::{EquipmentType, ItemStats, WeaponDamage, WeaponType, DamageType};
+        Item::new_equipment(
+            "Bow".to_string(),
+            EquipmentType::Weapon(WeaponType::TwoHanded),
+            ItemStats {
+                strength_bonus: 0,
+                dexterity_bonus: 0,
+                constitution_bonus: 0,
+                intelligence_bonus: 0,
+                wisdom_bonus: 0,
+                charisma_bonus: 0,
+                armor: 0,
+                damage: Some(WeaponDamage {
+                    min_damage: 1,
+                    max_damage: 8,
+                    damage_type: DamageType::Piercing,
+                    range: Some(6),
+                    finesse: false,
+                }),
+                defenses: Default::default(),
+            },
+            None,
+            1,
+        )
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character, Direction, HexPosition,
+    area::AreaShape,
+    character::Ability,
+    check::{Check, SaveOutcome},
+    dice::{Expr, RollMode, RollResult},
+    effect::StatusEffect,
+    item::DamageType,
+};
+use super::{ActionKind, Combat, CombatError, CombatEvent, FriendlyFire};
+
+/// Damage (and optionally a status effect) dealt to everyone in an area
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AreaEffect {
+    pub shape: AreaShape,
+    /// How far from the source the shape can be placed, in hexes
+    pub range: i32,
+    /// Rolled once and shared by every target, as with a fireball
+    pub damage: Expr,
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub save: Option<AreaSave>,
+    /// Applied to targets that fail the save, or to all of them without one
+    #[serde(default)]
+    pub status: Option<StatusEffect>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AreaSave {
+    pub ability: Ability,
+    pub dc: i32,
+    #[serde(default)]
+    pub on_success: SaveOutcome,
+}
+
+/// How one target fared against an area effect
+#[derive(Debug, Clone)]
+pub struct AreaHit {
+    pub target_id: String,
+    pub save_roll: Option<RollResult>,
+    pub saved: bool,
+    /// Damage after the save, before resistances
+    pub raw_damage: i32,
+    pub damage: i32,
+}
+
+impl AreaEffect {
+    /// 8d6 fire in a 4-hex burst up to 30 hexes away, dexterity save (DC 15) for half
+    pub fn fireball() -> Self {
+        Self {
+            shape: AreaShape::Burst { radius: 4 },
+            range: 30,
+            damage: Expr::parse("8d6").expect("valid dice notation"),
+            damage_type: DamageType::Fire,
+            save: Some(AreaSave { ability: Ability::Dexterity, dc: 15, on_success: SaveOutcome::HalfDamage }),
+            status: None,
+        }
+    }
+
+    /// 2d6 fire in a 3-hex cone from the breather's own hex, dexterity save (DC 13) for half
+    pub fn breath_weapon(direction: Direction) -> Self {
+        Self {
+            shape: AreaShape::Cone { direction, length: 3 },
+            range: 0,
+            damage: Expr::parse("2d6").expect("valid dice notation"),
+            damage_type: DamageType::Fire,
+            save: Some(AreaSave { ability: Ability::Dexterity, dc: 13, on_success: SaveOutcome::HalfDamage }),
+            status: None,
+        }
+    }
+}
+
+impl Combat {
+    /// Unleash an area effect from `source_id` with its shape placed at `origin`
+    ///
+    /// The origin must lie within the effect's range of the source, which
+    /// needs line of sight to it, and only targets the origin
+    /// can see are affected, so walls shelter whoever stands behind them.
+    /// Allies of the source are spared unless friendly fire is allowed.
+    /// Takes the source's action.
+    pub fn area_attack(
+        &mut self,
+        source_id: &str,
+        origin: HexPosition,
+        effect: &AreaEffect,
+    ) -> Result<Vec<AreaHit>, CombatError> {
+        self.undoable(|combat| combat.resolve_area(source_id, origin, effect))
+    }
+
+    fn resolve_area(
+        &mut self,
+        source_id: &str,
+        origin: HexPosition,
+        effect: &AreaEffect,
+    ) -> Result<Vec<AreaHit>, CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        let source = self.get_participant(source_id).ok_or(CombatError::ParticipantNotFound)?;
+        if !source.is_alive() {
+            return Err(CombatError::AttackerDown);
+        }
+        if !source.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
+        let distance = source.position.distance(&origin);
+        if distance > effect.range {
+            return Err(CombatError::OutOfRange { distance, range: effect.range });
+        }
+        let sees = |from: HexPosition, to: HexPosition| {
+            self.grid.as_ref().is_none_or(|grid| grid.has_line_of_sight(from, to))
+        };
+        if !sees(source.position, origin) {
+            return Err(CombatError::NoLineOfSight);
+        }
+
+        let hexes = effect.shape.hexes(origin);
+        let spare_allies = self.friendly_fire == FriendlyFire::Prevented;
+        let targets: Vec<String> = self
+            .participants
+            .alive()
+            .filter(|c| hexes.iter().any(|h| h.q == c.position.q && h.r == c.position.r))
+            .filter(|c| sees(origin, c.position))
+            .filter(|c| !(spare_allies && self.is_allied(source_id, &c.id)))
+            .map(|c| c.id.clone())
+            .collect();
+        self.turn_budget(source_id)?.spend(ActionKind::Action)?;
+
+        let damage = effect.damage.evaluate(self.roller.as_mut()).total.max(0);
+        self.record(CombatEvent::AreaAttacked {
+            source_id: source_id.to_string(),
+            origin,
+            shape: effect.shape,
+            damage,
+            damage_type: effect.damage_type,
+        });
+
+        let mut hits = Vec::new();
+        for id in targets {
+            let Some(target) = self.participants.get_mut(&id) else {
+                continue;
+            };
+            let save = effect.save.map(|save| {
+                target.attempt(Check::Save(save.ability), save.dc, RollMode::Normal, self.roller.as_mut())
+            });
+            let saved = save.as_ref().is_some_and(|s| s.success);
+            let save_roll = save.map(|s| s.roll);
+            let raw_damage = match (saved, effect.save.map(|s| s.on_success)) {
+                (true, Some(SaveOutcome::HalfDamage)) => damage / 2,
+                (true, _) => 0,
+                (false, _) => damage,
+            };
+
+            let dealt = target.take_damage(raw_damage, effect.damage_type);
+            if !saved {
+                if let Some(status) = &effect.status {
+                    target.add_effect(status.clone());
+                }
+            }
+            let died = !target.is_alive();
+
+            self.record(CombatEvent::AreaHit {
+                id: id.clone(),
+                save_roll: save_roll.clone(),
+                saved,
+                damage: dealt,
+            });
+            if died {
+                self.record(CombatEvent::Died { id: id.clone() });
+            }
+            self.maintain_concentration(&id, dealt);
+            hits.push(AreaHit {
+                target_id: id,
+                save_roll,
+                saved,
+                raw_damage,
+                damage: dealt,
+            });
+        }
+
+        self.check_victory();
+        Ok(hits)
+    }
+
+    /// Living participants standing in an area, ignoring line of sight
+    pub fn participants_in_area(&self, shape: &AreaShape, origin: HexPosition) -> Vec<&Character> {
+        self.participants
+            .alive()
+            .filter(|c| shape.contains(origin, &c.position))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        TerrainType,
+        combat::test_support::{character_at, open_grid},
+        dice::ScriptedRoller,
+    };
+
+    #[test]
+    fn test_fireball_saves_and_walls() {
+        let mut grid = open_grid(10);
+        grid.add_cell(HexPosition::new_2d(5, 3), TerrainType::Wall, 0);
+
+        // Initiative for four, 8d6 totalling 24, then two saves: a fail and a success
+        let faces = [10, 10, 10, 10, 3, 3, 3, 3, 3, 3, 3, 3, 4, 17];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        combat.set_grid(grid);
+        combat.add_participant(character_at("Wizard", 0, 5));
+        combat.add_participant(character_at("Orc", 5, 5));
+        combat.add_participant(character_at("Goblin", 6, 5));
+        combat.add_participant(character_at("Hidden", 5, 2));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+
+        let hits = combat.area_attack(&ids[0], HexPosition::new_2d(5, 4), &AreaEffect::fireball()).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!((hits[0].saved, hits[0].damage), (false, 24));
+        assert_eq!((hits[1].saved, hits[1].damage), (true, 12));
+
+        // Out of the blast and behind the wall
+        assert_eq!(combat.get_participant(&ids[0]).unwrap().health.current, 20);
+        assert_eq!(combat.get_participant(&ids[3]).unwrap().health.current, 20);
+        assert!(!combat.get_participant(&ids[1]).unwrap().is_alive());
+    }
+
+    #[test]
+    fn test_area_out_of_range() {
+        // Initiative for two, then 8d6 totalling 8 and a failed save once in range
+        let faces = [10, 10, 1, 1, 1, 1, 1, 1, 1, 1, 4];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        combat.add_participant(character_at("Wizard", 0, 0));
+        combat.add_participant(character_at("Orc", 5, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+
+        let short = AreaEffect { range: 3, ..AreaEffect::fireball() };
+        assert_eq!(
+            combat.area_attack(&ids[0], HexPosition::new_2d(5, 0), &short).unwrap_err(),
+            CombatError::OutOfRange { distance: 5, range: 3 }
+        );
+        // Nothing was spent on the failed attempt
+        assert_eq!(combat.get_participant(&ids[1]).unwrap().health.current, 20);
+        let hits = combat.area_attack(&ids[0], HexPosition::new_2d(3, 0), &short).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].target_id.as_str(), hits[0].damage), (ids[1].as_str(), 8));
+    }
+}
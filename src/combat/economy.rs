@@ -0,0 +1,186 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::Character;
+
+/// The parts of a turn a participant can spend
+///
+/// Movement is not listed: it is spent hex by hex from `Character::movement`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ActionKind {
+    /// Attacking, using an item, unleashing an area effect
+    Action,
+    /// Quick extras such as an off-hand attack
+    BonusAction,
+    /// Once per round, usually on someone else's turn
+    Reaction,
+    /// Drawing or swapping a weapon, opening a door and the like
+    FreeInteraction,
+}
+
+/// Why a participant cannot do something right now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionError {
+    /// Only the participant taking their turn may act, reactions aside
+    NotYourTurn,
+    /// That part of the turn (or round, for reactions) is already spent
+    Spent(ActionKind),
+}
+
+/// What the participant taking their turn has left to spend
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnBudget {
+    pub id: String,
+    pub actions: u32,
+    pub bonus_actions: u32,
+    pub free_interactions: u32,
+    /// Attacks left from an Attack action already begun (extra attacks)
+    pub attacks: u32,
+}
+
+impl TurnBudget {
+    /// A fresh turn: one action, one bonus action and one free interaction
+    pub fn new(character: &Character) -> Self {
+        Self {
+            id: character.id.clone(),
+            actions: 1,
+            bonus_actions: 1,
+            free_interactions: 1,
+            attacks: 0,
+        }
+    }
+
+    /// Nothing has been spent yet this turn
+    pub fn is_untouched(&self) -> bool {
+        (self.actions, self.bonus_actions, self.free_interactions, self.attacks) == (1, 1, 1, 0)
+    }
+
+    pub fn has(&self, kind: ActionKind) -> bool {
+        self.remaining(kind).is_some_and(|left| left > 0)
+    }
+
+    /// Whether another attack can be made, from the current Attack action or a new one
+    pub fn can_attack(&self) -> bool {
+        self.attacks > 0 || self.actions > 0
+    }
+
+    /// Reactions are tracked per round by `Combat`, not here
+    fn remaining(&self, kind: ActionKind) -> Option<u32> {
+        match kind {
+            ActionKind::Action => Some(self.actions),
+            ActionKind::BonusAction => Some(self.bonus_actions),
+            ActionKind::FreeInteraction => Some(self.free_interactions),
+            ActionKind::Reaction => None,
+        }
+    }
+
+    pub(super) fn spend(&mut self, kind: ActionKind) -> Result<(), ActionError> {
+        let left = match kind {
+            ActionKind::Action => &mut self.actions,
+            ActionKind::BonusAction => &mut self.bonus_actions,
+            ActionKind::FreeInteraction => &mut self.free_interactions,
+            ActionKind::Reaction => return Err(ActionError::Spent(kind)),
+        };
+        if *left == 0 {
+            return Err(ActionError::Spent(kind));
+        }
+        *left -= 1;
+        Ok(())
+    }
+
+    /// Make one attack, taking the Attack action if none is under way
+    ///
+    /// Taking the action grants `per_action` attacks, this one included.
+    pub(super) fn spend_attack(&mut self, per_action: u32) -> Result<(), ActionError> {
+        if self.attacks == 0 {
+            self.spend(ActionKind::Action)?;
+            self.attacks = per_action.max(1);
+        }
+        self.attacks -= 1;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionKind::Action => write!(f, "action"),
+            ActionKind::BonusAction => write!(f, "bonus action"),
+            ActionKind::Reaction => write!(f, "reaction"),
+            ActionKind::FreeInteraction => write!(f, "free interaction"),
+        }
+    }
+}
+
+impl fmt::Display for ActionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionError::NotYourTurn => write!(f, "it is not this participant's turn"),
+            ActionError::Spent(ActionKind::Reaction) => write!(f, "reaction already used this round"),
+            ActionError::Spent(kind) => write!(f, "no {} left this turn", kind),
+        }
+    }
+}
+
+impl std::error::Error for ActionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        combat::{Combat, CombatError, test_support::character_at},
+        dice::ScriptedRoller,
+    };
+
+    fn character(name: &str, level: i32) -> Character {
+        let mut character = character_at(name, 0, 0);
+        character.level = level;
+        character
+    }
+
+    #[test]
+    fn test_budget_spending() {
+        let mut budget = TurnBudget::new(&character("Fighter", 11));
+        let per_action = 3;
+        for _ in 0..per_action {
+            budget.spend_attack(per_action).unwrap();
+        }
+        assert!(!budget.can_attack());
+        assert_eq!(budget.spend_attack(per_action), Err(ActionError::Spent(ActionKind::Action)));
+
+        budget.spend(ActionKind::BonusAction).unwrap();
+        assert!(!budget.has(ActionKind::BonusAction));
+        assert!(budget.has(ActionKind::FreeInteraction));
+        assert_eq!(
+            ActionError::Spent(ActionKind::BonusAction).to_string(),
+            "no bonus action left this turn"
+        );
+    }
+
+    #[test]
+    fn test_attacks_per_turn() {
+        // Initiative, then two misses from the level 5 fighter
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 10, 2, 2])));
+        combat.add_participant(character("Veteran", 5));
+        combat.add_participant(character("Recruit", 1));
+        let veteran = combat.participants()[0].id.clone();
+        let recruit = combat.participants()[1].id.clone();
+
+        let not_your_turn = CombatError::Action(ActionError::NotYourTurn);
+        assert_eq!(combat.attack(&veteran, &recruit).unwrap_err(), not_your_turn);
+        combat.next_turn();
+        assert_eq!(combat.attack(&recruit, &veteran).unwrap_err(), not_your_turn);
+
+        combat.attack(&veteran, &recruit).unwrap();
+        combat.attack(&veteran, &recruit).unwrap();
+        assert_eq!(
+            combat.attack(&veteran, &recruit).unwrap_err(),
+            CombatError::Action(ActionError::Spent(ActionKind::Action))
+        );
+        assert!(combat.can_take(&recruit, ActionKind::Reaction));
+        assert!(!combat.can_take(&recruit, ActionKind::Action));
+
+        // A fresh budget on the next turn
+        combat.next_turn();
+        assert!(combat.can_attack(&recruit));
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use super::CombatError;
+
+/// One participant's place in the initiative order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InitiativeEntry {
+    pub participant_id: String,
+    /// d20 + dexterity modifier (or the adopted value after delaying)
+    pub total: i32,
+    /// Dexterity score, used to break ties on `total`
+    pub dexterity: i32,
+}
+
+/// Initiative-ordered turn tracking with a round counter
+///
+/// Remaining ties after dexterity are broken by joining order: whoever was
+/// added to the encounter first acts first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnOrder {
+    entries: Vec<InitiativeEntry>,
+    current: usize,
+    round: u32,
+    /// The current slot was vacated (removed or delayed) or the encounter
+    /// has not started, so the entry now in it has not acted yet
+    vacated: bool,
+    readied: HashSet<String>,
+}
+
+impl TurnOrder {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            current: 0,
+            round: 1,
+            vacated: true,
+            readied: HashSet::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[InitiativeEntry] {
+        &self.entries
+    }
+
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn current_id(&self) -> Option<&str> {
+        if self.vacated {
+            return None;
+        }
+        self.entries.get(self.current).map(|e| e.participant_id.as_str())
+    }
+
+    /// Insert an entry at its initiative position without changing whose turn it is
+    pub fn insert(&mut self, entry: InitiativeEntry) {
+        let index = self
+            .entries
+            .iter()
+            .position(|e| (entry.total, entry.dexterity) > (e.total, e.dexterity))
+            .unwrap_or(self.entries.len());
+        self.insert_at(index, entry);
+    }
+
+    fn insert_at(&mut self, index: usize, entry: InitiativeEntry) {
+        let shifts_current = if self.vacated {
+            index < self.current
+        } else {
+            index <= self.current
+        };
+        if shifts_current {
+            self.current += 1;
+        }
+        self.entries.insert(index, entry);
+    }
+
+    pub fn remove(&mut self, participant_id: &str) -> Option<InitiativeEntry> {
+        let index = self.entries.iter().position(|e| e.participant_id == participant_id)?;
+        let entry = self.entries.remove(index);
+        self.readied.remove(participant_id);
+
+        if index < self.current {
+            self.current -= 1;
+        } else if index == self.current && !self.vacated {
+            // Whoever slides into this slot still gets their turn
+            self.vacated = true;
+        }
+        Some(entry)
+    }
+
+    /// Move to the next living participant, returning their id
+    pub fn advance(&mut self, is_alive: impl Fn(&str) -> bool) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        for _ in 0..=self.entries.len() {
+            if self.vacated {
+                self.vacated = false;
+            } else {
+                self.current += 1;
+            }
+            if self.current >= self.entries.len() {
+                self.current = 0;
+                self.round += 1;
+            }
+
+            let id = &self.entries[self.current].participant_id;
+            if is_alive(id) {
+                // A readied action lapses once its owner's next turn begins
+                self.readied.remove(id);
+                return Some(self.entries[self.current].participant_id.as_str());
+            }
+        }
+
+        None
+    }
+
+    /// Postpone the current turn until directly after `after_id` this round
+    pub fn delay(&mut self, after_id: &str) -> Result<(), CombatError> {
+        if self.vacated || self.current >= self.entries.len() {
+            return Err(CombatError::NoActiveTurn);
+        }
+        let target = self
+            .entries
+            .iter()
+            .position(|e| e.participant_id == after_id)
+            .ok_or(CombatError::NotInTurnOrder)?;
+        if target <= self.current {
+            return Err(CombatError::CannotDelayPastSelf);
+        }
+
+        let mut entry = self.entries.remove(self.current);
+        entry.total = self.entries[target - 1].total;
+        self.entries.insert(target, entry);
+        self.vacated = true;
+        Ok(())
+    }
+
+    /// Give up the rest of the current turn in exchange for a readied action
+    pub fn ready(&mut self) -> Result<(), CombatError> {
+        let id = self.current_id().ok_or(CombatError::NoActiveTurn)?.to_string();
+        self.readied.insert(id);
+        Ok(())
+    }
+
+    pub fn is_readied(&self, participant_id: &str) -> bool {
+        self.readied.contains(participant_id)
+    }
+
+    /// Spend a readied action, returning false if none was held
+    pub fn take_readied(&mut self, participant_id: &str) -> bool {
+        self.readied.remove(participant_id)
+    }
+}
+
+impl Default for TurnOrder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, total: i32, dexterity: i32) -> InitiativeEntry {
+        InitiativeEntry {
+            participant_id: id.to_string(),
+            total,
+            dexterity,
+        }
+    }
+
+    fn ids(order: &TurnOrder) -> Vec<&str> {
+        order.entries().iter().map(|e| e.participant_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ordering_and_ties() {
+        let mut order = TurnOrder::new();
+        order.insert(entry("a", 12, 10));
+        order.insert(entry("b", 18, 10));
+        order.insert(entry("c", 12, 14));
+        order.insert(entry("d", 12, 10));
+        assert_eq!(ids(&order), vec!["b", "c", "a", "d"]);
+    }
+
+    #[test]
+    fn test_mid_encounter_changes_preserve_turn() {
+        let mut order = TurnOrder::new();
+        order.insert(entry("a", 20, 10));
+        order.insert(entry("b", 10, 10));
+        order.insert(entry("c", 5, 10));
+
+        assert_eq!(order.current_id(), None);
+        assert_eq!(order.advance(|_| true), Some("a"));
+        assert_eq!(order.advance(|_| true), Some("b"));
+        order.insert(entry("x", 15, 10));
+        assert_eq!(order.current_id(), Some("b"));
+
+        // Removing the active participant hands the slot to the next one
+        order.remove("b");
+        assert_eq!(order.advance(|_| true), Some("c"));
+        assert_eq!(order.round(), 1);
+
+        // Dead participants are skipped and wrapping starts a new round
+        assert_eq!(order.advance(|id| id != "a"), Some("x"));
+        assert_eq!(order.round(), 2);
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut order = TurnOrder::new();
+        order.insert(entry("a", 20, 10));
+        order.insert(entry("b", 10, 10));
+        order.insert(entry("c", 5, 10));
+
+        assert_eq!(order.advance(|_| true), Some("a"));
+        order.delay("b").unwrap();
+        assert_eq!(order.advance(|_| true), Some("b"));
+        assert_eq!(order.advance(|_| true), Some("a"));
+        assert_eq!(order.advance(|_| true), Some("c"));
+        assert_eq!(ids(&order), vec!["b", "a", "c"]);
+        assert_eq!(order.delay("b"), Err(CombatError::CannotDelayPastSelf));
+        assert_eq!(order.delay("z"), Err(CombatError::NotInTurnOrder));
+    }
+}
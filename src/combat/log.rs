@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{
+    HexPosition,
+    area::AreaShape,
+    dice::RollResult,
+    effect::EffectTick,
+    item::DamageType,
+};
+use super::{AiAction, Reaction, VictoryCondition};
+
+/// Everything that can happen during an encounter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum CombatEvent {
+    Joined {
+        id: String,
+        name: String,
+        initiative: i32,
+        position: HexPosition,
+        health: i32,
+        max_health: i32,
+    },
+    Left {
+        id: String,
+    },
+    TurnStarted {
+        id: String,
+    },
+    TurnDelayed {
+        id: String,
+        after_id: String,
+    },
+    ActionReadied {
+        id: String,
+    },
+    Moved {
+        id: String,
+        path: Vec<HexPosition>,
+        cost: i32,
+    },
+    Attacked {
+        attacker_id: String,
+        defender_id: String,
+        attack_roll: RollResult,
+        armor_class: i32,
+        hit: bool,
+        critical: bool,
+        damage_roll: Option<RollResult>,
+        raw_damage: i32,
+        damage: i32,
+        damage_type: DamageType,
+    },
+    EffectTicked {
+        id: String,
+        tick: EffectTick,
+    },
+    AreaAttacked {
+        source_id: String,
+        origin: HexPosition,
+        shape: AreaShape,
+        damage: i32,
+        damage_type: DamageType,
+    },
+    AreaHit {
+        id: String,
+        save_roll: Option<RollResult>,
+        saved: bool,
+        damage: i32,
+    },
+    Reacted {
+        id: String,
+        reaction: Reaction,
+    },
+    /// An AI chose an action the rules refused, which ended its turn
+    AiActionFailed {
+        id: String,
+        action: AiAction,
+        reason: String,
+    },
+    ItemUsed {
+        id: String,
+        item: String,
+        healed: i32,
+        #[serde(default)]
+        mana: i32,
+    },
+    SpellCast {
+        caster_id: String,
+        spell: String,
+        target: HexPosition,
+    },
+    SpellHit {
+        id: String,
+        attack_roll: Option<RollResult>,
+        save_roll: Option<RollResult>,
+        /// The attack hit, the save failed, or the spell needed neither
+        landed: bool,
+        damage: i32,
+        healed: i32,
+    },
+    ConcentrationBroken {
+        id: String,
+        spell: String,
+    },
+    Died {
+        id: String,
+    },
+    Ended {
+        winners: Vec<String>,
+        condition: VictoryCondition,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub sequence: u64,
+    pub round: u32,
+    pub event: CombatEvent,
+}
+
+/// Ordered record of every event in an encounter
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CombatLog {
+    entries: Vec<LoggedEvent>,
+}
+
+/// Participant state rebuilt by replaying a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayedParticipant {
+    pub name: String,
+    pub position: HexPosition,
+    pub health: i32,
+    pub max_health: i32,
+    pub alive: bool,
+}
+
+impl CombatLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, round: u32, event: CombatEvent) {
+        self.entries.push(LoggedEvent {
+            sequence: self.entries.len() as u64,
+            round,
+            event,
+        });
+    }
+
+    pub fn entries(&self) -> &[LoggedEvent] {
+        &self.entries
+    }
+
+    /// Drop everything logged after the first `len` entries
+    pub(super) fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn round(&self, round: u32) -> impl Iterator<Item = &LoggedEvent> {
+        self.entries.iter().filter(move |e| e.round == round)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Participant names by id, as announced when they joined
+    pub fn names(&self) -> HashMap<&str, &str> {
+        self.entries
+            .iter()
+            .filter_map(|e| match &e.event {
+                CombatEvent::Joined { id, name, .. } => Some((id.as_str(), name.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Rebuild participant state from the first `count` events
+    pub fn replay(&self, count: usize) -> HashMap<String, ReplayedParticipant> {
+        let mut state: HashMap<String, ReplayedParticipant> = HashMap::new();
+
+        for entry in self.entries.iter().take(count) {
+            match &entry.event {
+                CombatEvent::Joined { id, name, position, health, max_health, .. } => {
+                    state.insert(id.clone(), ReplayedParticipant {
+                        name: name.clone(),
+                        position: *position,
+                        health: *health,
+                        max_health: *max_health,
+                        alive: *health > 0,
+                    });
+                }
+                CombatEvent::Left { id } => {
+                    state.remove(id);
+                }
+                CombatEvent::Moved { id, path, .. } => {
+                    if let (Some(p), Some(end)) = (state.get_mut(id), path.last()) {
+                        p.position = *end;
+                    }
+                }
+                CombatEvent::Attacked { defender_id: id, damage, .. } | CombatEvent::AreaHit { id, damage, .. } => {
+                    if let Some(p) = state.get_mut(id) {
+                        p.health -= damage;
+                    }
+                }
+                CombatEvent::EffectTicked { id, tick } => {
+                    if let Some(p) = state.get_mut(id) {
+                        match tick {
+                            EffectTick::Damaged { amount, .. } => p.health -= amount,
+                            EffectTick::Healed { amount, .. } => p.health += amount,
+                            EffectTick::Expired { .. } => {}
+                        }
+                    }
+                }
+                CombatEvent::ItemUsed { id, healed, .. } => {
+                    if let Some(p) = state.get_mut(id) {
+                        p.health += healed;
+                    }
+                }
+                CombatEvent::SpellHit { id, damage, healed, .. } => {
+                    if let Some(p) = state.get_mut(id) {
+                        p.health += healed - damage;
+                    }
+                }
+                CombatEvent::Died { id } => {
+                    if let Some(p) = state.get_mut(id) {
+                        p.alive = false;
+                    }
+                }
+                CombatEvent::TurnStarted { .. }
+                | CombatEvent::TurnDelayed { .. }
+                | CombatEvent::ActionReadied { .. }
+                | CombatEvent::AiActionFailed { .. }
+                | CombatEvent::Reacted { .. }
+                | CombatEvent::AreaAttacked { .. }
+                | CombatEvent::SpellCast { .. }
+                | CombatEvent::ConcentrationBroken { .. }
+                | CombatEvent::Ended { .. } => {}
+            }
+        }
+
+        state
+    }
+
+    /// Human-readable recap, one line per event
+    pub fn render(&self) -> String {
+        let names = self.names();
+        let name = |id: &str| names.get(id).copied().unwrap_or(id).to_string();
+
+        let mut lines = Vec::new();
+        for entry in &self.entries {
+            let text = match &entry.event {
+                CombatEvent::Joined { name, initiative, .. } => {
+                    format!("{} joins the fight (initiative {})", name, initiative)
+                }
+                CombatEvent::Left { id } => format!("{} leaves the fight", name(id)),
+                CombatEvent::TurnStarted { id } => format!("{}'s turn", name(id)),
+                CombatEvent::TurnDelayed { id, after_id } => {
+                    format!("{} delays until after {}", name(id), name(after_id))
+                }
+                CombatEvent::ActionReadied { id } => format!("{} readies an action", name(id)),
+                CombatEvent::Moved { id, path, cost } => match (path.first(), path.last()) {
+                    (Some(from), Some(to)) => format!(
+                        "{} moves from ({}, {}) to ({}, {}) using {} movement",
+                        name(id), from.q, from.r, to.q, to.r, cost
+                    ),
+                    _ => format!("{} stays put", name(id)),
+                },
+                CombatEvent::Attacked {
+                    attacker_id, defender_id, attack_roll, armor_class, hit, critical, raw_damage, damage, damage_type, ..
+                } => {
+                    let mut text = format!(
+                        "{} attacks {}: {} vs AC {}",
+                        name(attacker_id), name(defender_id), attack_roll, armor_class
+                    );
+                    if *hit {
+                        text.push_str(&format!(
+                            " - {} for {} {:?} damage",
+                            if *critical { "critical hit" } else { "hit" },
+                            damage,
+                            damage_type
+                        ));
+                        if raw_damage != damage {
+                            text.push_str(&format!(" ({} before defenses)", raw_damage));
+                        }
+                    } else {
+                        text.push_str(" - miss");
+                    }
+                    text
+                }
+                CombatEvent::EffectTicked { id, tick } => match tick {
+                    EffectTick::Damaged { kind, amount, damage_type } => {
+                        format!("{} takes {} {:?} damage from {:?}", name(id), amount, damage_type, kind)
+                    }
+                    EffectTick::Healed { kind, amount } => {
+                        format!("{} heals {} from {:?}", name(id), amount, kind)
+                    }
+                    EffectTick::Expired { kind } => format!("{:?} wears off {}", kind, name(id)),
+                },
+                CombatEvent::AreaAttacked { source_id, origin, shape, damage, damage_type } => format!(
+                    "{} unleashes a {:?} at ({}, {}) for {} {:?} damage",
+                    name(source_id), shape, origin.q, origin.r, damage, damage_type
+                ),
+                CombatEvent::AreaHit { id, save_roll, saved, damage } => match save_roll {
+                    Some(roll) => format!(
+                        "{} {} the save ({}) and takes {} damage",
+                        name(id), if *saved { "makes" } else { "fails" }, roll, damage
+                    ),
+                    None => format!("{} takes {} damage", name(id), damage),
+                },
+                CombatEvent::Reacted { id, reaction } => match reaction {
+                    Reaction::OpportunityAttack => format!("{} takes an opportunity attack", name(id)),
+                    Reaction::ArmorBonus(bonus) => format!("{} blocks (+{} AC)", name(id), bonus),
+                    Reaction::ReduceDamage(amount) => format!("{} parries ({} less damage)", name(id), amount),
+                    Reaction::Counter => format!("{} counters the spell", name(id)),
+                },
+                CombatEvent::AiActionFailed { id, action, reason } => {
+                    let attempt = match action {
+                        AiAction::Move(to) => format!("move to ({}, {})", to.q, to.r),
+                        AiAction::Attack { target_id } => format!("attack {}", name(target_id)),
+                        AiAction::UseItem { .. } => "use an item".to_string(),
+                        AiAction::EndTurn => "end the turn".to_string(),
+                    };
+                    format!("{} tries to {} but cannot: {}", name(id), attempt, reason)
+                }
+                CombatEvent::ItemUsed { id, item, healed, mana } => {
+                    if *mana > 0 {
+                        format!("{} uses {}, recovering {} mana", name(id), item, mana)
+                    } else {
+                        format!("{} uses {}, recovering {} health", name(id), item, healed)
+                    }
+                }
+                CombatEvent::SpellCast { caster_id, spell, target } => {
+                    format!("{} casts {} at ({}, {})", name(caster_id), spell, target.q, target.r)
+                }
+                CombatEvent::SpellHit { id, attack_roll, save_roll, landed, damage, healed } => {
+                    let mut text = match (attack_roll, save_roll) {
+                        (Some(roll), _) => {
+                            format!("{} is {} ({})", name(id), if *landed { "hit" } else { "missed" }, roll)
+                        }
+                        (_, Some(roll)) => {
+                            format!("{} {} the save ({})", name(id), if *landed { "fails" } else { "makes" }, roll)
+                        }
+                        _ => format!("{} is affected", name(id)),
+                    };
+                    if *damage > 0 {
+                        text.push_str(&format!(" and takes {} damage", damage));
+                    }
+                    if *healed > 0 {
+                        text.push_str(&format!(" and heals {}", healed));
+                    }
+                    text
+                }
+                CombatEvent::ConcentrationBroken { id, spell } => {
+                    format!("{} loses concentration on {}", name(id), spell)
+                }
+                CombatEvent::Died { id } => format!("{} falls", name(id)),
+                CombatEvent::Ended { winners, condition } => {
+                    let winners: Vec<String> = winners.iter().map(|w| name(w)).collect();
+                    if winners.is_empty() {
+                        format!("The encounter ends with no one standing ({})", condition)
+                    } else {
+                        format!("{} win ({})", winners.join(" and "), condition)
+                    }
+                }
+            };
+            lines.push(format!("[round {}] {}", entry.round, text));
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for CombatLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Character, Combat,
+        character::CharacterStats,
+        dice::ScriptedRoller,
+        item::RaceType,
+    };
+
+    #[test]
+    fn test_log_records_replays_and_exports() {
+        let stats = CharacterStats {
+            strength: 14,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10,
+        };
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 10, 18, 4])));
+        combat.add_participant(Character::new("Fighter".to_string(), RaceType::Human, stats.clone()));
+        combat.add_participant(Character::new("Bandit".to_string(), RaceType::Human, stats));
+        let fighter_id = combat.participants()[0].id.clone();
+        let bandit_id = combat.participants()[1].id.clone();
+
+        combat.next_turn();
+        let result = combat.attack(&fighter_id, &bandit_id).unwrap();
+        assert!(result.hit);
+
+        let log = combat.log();
+        assert!(matches!(log.entries()[2].event, CombatEvent::TurnStarted { .. }));
+        assert!(matches!(log.entries()[3].event, CombatEvent::Attacked { hit: true, armor_class: 10, .. }));
+
+        let replayed = log.replay(log.len());
+        assert_eq!(replayed[&bandit_id].health, combat.participants()[1].health.current);
+
+        let text = log.render();
+        assert!(text.contains("[round 1] Fighter attacks Bandit: 1d20 [18] + 2 = 20 vs AC 10 - hit for 6 Blunt damage"));
+
+        let restored = CombatLog::from_json(&log.to_json().unwrap()).unwrap();
+        assert_eq!(&restored, log);
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use crate::HexPosition;
+use super::Combat;
+
+/// Something another participant may spend their reaction on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReactionTrigger {
+    /// `mover_id` is stepping out of a hex the reactor threatens
+    LeavingReach { mover_id: String, from: HexPosition, to: HexPosition },
+    /// An attack roll against `defender_id` is about to hit
+    Attacked { attacker_id: String, defender_id: String, attack_total: i32, armor_class: i32, critical: bool },
+    /// `caster_id` is casting a spell
+    SpellCast { caster_id: String, spell: String },
+}
+
+/// What a reaction does to the triggering action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reaction {
+    /// Strike the participant leaving reach
+    OpportunityAttack,
+    /// Raise armor class against the triggering attack, e.g. a shield block
+    ArmorBonus(i32),
+    /// Take less damage from the triggering hit, e.g. a parry
+    ReduceDamage(i32),
+    /// Stop the triggering spell, e.g. a counterspell
+    Counter,
+}
+
+/// Decides whether a participant spends their reaction on a trigger
+///
+/// Only participants eligible for the trigger are asked: those an enemy is
+/// leaving, the target of an attack, or enemies of a spellcaster.
+pub trait Reactor {
+    fn react(&mut self, combat: &Combat, reactor_id: &str, trigger: &ReactionTrigger) -> Option<Reaction>;
+}
+
+/// Takes every opportunity attack offered and nothing else; used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpportunityAttacks;
+
+/// Parries hits for `reduction` damage, otherwise takes opportunity attacks
+#[derive(Debug, Clone, Copy)]
+pub struct Parry {
+    pub reduction: i32,
+}
+
+/// Raises a shield when it would turn a hit into a miss, otherwise takes opportunity attacks
+#[derive(Debug, Clone, Copy)]
+pub struct ShieldBlock {
+    pub bonus: i32,
+}
+
+/// Counters enemy spells, otherwise takes opportunity attacks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counterspell;
+
+impl ReactionTrigger {
+    /// Whether `reaction` makes sense as an answer to this trigger
+    pub fn allows(&self, reaction: &Reaction) -> bool {
+        matches!(
+            (self, reaction),
+            (ReactionTrigger::LeavingReach { .. }, Reaction::OpportunityAttack)
+                | (ReactionTrigger::Attacked { .. }, Reaction::ArmorBonus(_))
+                | (ReactionTrigger::Attacked { .. }, Reaction::ReduceDamage(_))
+                | (ReactionTrigger::SpellCast { .. }, Reaction::Counter)
+        )
+    }
+}
+
+fn opportunity_attack(trigger: &ReactionTrigger) -> Option<Reaction> {
+    match trigger {
+        ReactionTrigger::LeavingReach { .. } => Some(Reaction::OpportunityAttack),
+        _ => None,
+    }
+}
+
+impl Reactor for OpportunityAttacks {
+    fn react(&mut self, _combat: &Combat, _reactor_id: &str, trigger: &ReactionTrigger) -> Option<Reaction> {
+        opportunity_attack(trigger)
+    }
+}
+
+impl Reactor for Parry {
+    fn react(&mut self, _combat: &Combat, _reactor_id: &str, trigger: &ReactionTrigger) -> Option<Reaction> {
+        match trigger {
+            ReactionTrigger::Attacked { .. } => Some(Reaction::ReduceDamage(self.reduction)),
+            _ => opportunity_attack(trigger),
+        }
+    }
+}
+
+impl Reactor for ShieldBlock {
+    fn react(&mut self, _combat: &Combat, _reactor_id: &str, trigger: &ReactionTrigger) -> Option<Reaction> {
+        match trigger {
+            ReactionTrigger::Attacked { attack_total, armor_class, critical: false, .. }
+                if *attack_total < armor_class + self.bonus =>
+            {
+                Some(Reaction::ArmorBonus(self.bonus))
+            }
+            _ => opportunity_attack(trigger),
+        }
+    }
+}
+
+impl Reactor for Counterspell {
+    fn react(&mut self, _combat: &Combat, _reactor_id: &str, trigger: &ReactionTrigger) -> Option<Reaction> {
+        match trigger {
+            ReactionTrigger::SpellCast { .. } => Some(Reaction::Counter),
+            _ => opportunity_attack(trigger),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        combat::{CombatEvent, test_support::{character_at, open_grid}},
+        dice::ScriptedRoller,
+    };
+
+    #[test]
+    fn test_opportunity_attack_once_per_round() {
+        // Initiative, then the sentry's opportunity attack hits for 3
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 10, 15, 3])));
+        combat.set_grid(open_grid(6));
+        combat.add_participant(character_at("Runner", 2, 2));
+        combat.add_participant(character_at("Sentry", 3, 2));
+        let (runner, sentry) = (combat.participants()[0].id.clone(), combat.participants()[1].id.clone());
+        combat.next_turn();
+
+        let walked = combat.move_participant(&runner, HexPosition::new_2d(0, 2)).unwrap();
+        assert_eq!(walked.len(), 3);
+        assert_eq!(combat.get_participant(&runner).unwrap().health.current, 17);
+        assert!(!combat.has_reaction(&sentry));
+
+        // Moving along the sentry's reach doesn't provoke, and its reaction is spent anyway
+        combat.move_participant(&runner, HexPosition::new_2d(2, 2)).unwrap();
+        combat.move_participant(&runner, HexPosition::new_2d(1, 2)).unwrap();
+        let reactions = combat.log().entries().iter().filter(|e| matches!(e.event, CombatEvent::Reacted { .. }));
+        assert_eq!(reactions.count(), 1);
+    }
+
+    #[test]
+    fn test_defensive_reactions() {
+        // Initiative for three, a 12 the shield turns aside, then a hit for 5 parried down to 2
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new([15, 10, 5, 12, 18, 5])));
+        // Two attacks with one action
+        let mut attacker = character_at("Attacker", 0, 0);
+        attacker.level = 5;
+        combat.add_participant(attacker);
+        combat.add_participant(character_at("Shield", 0, 0));
+        combat.add_participant(character_at("Duelist", 0, 0));
+        let shield = combat.participants()[1].id.clone();
+        let duelist = combat.participants()[2].id.clone();
+        let attacker = combat.participants()[0].id.clone();
+        combat.set_reactor(&shield, Box::new(ShieldBlock { bonus: 5 }));
+        combat.set_reactor(&duelist, Box::new(Parry { reduction: 3 }));
+        combat.next_turn();
+
+        assert!(!combat.attack(&attacker, &shield).unwrap().hit);
+        assert!(!combat.has_reaction(&shield));
+
+        let parried = combat.attack(&attacker, &duelist).unwrap();
+        assert_eq!((parried.raw_damage, parried.damage), (5, 2));
+
+        combat.set_reactor(&shield, Box::new(Counterspell));
+        let trigger = ReactionTrigger::SpellCast { caster_id: attacker, spell: "Fireball".to_string() };
+        // Both defenders have already spent their reaction this round
+        assert!(combat.trigger_reactions(&trigger).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::Character;
+
+/// The participants of an encounter, addressed by `Character::id`
+///
+/// Iteration follows joining order, which stays stable as others join or leave.
+/// Mutable access is crate-only, since changing an id through it would leave
+/// `index` stale.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Character>", into = "Vec<Character>")]
+pub struct Roster {
+    characters: Vec<Character>,
+    /// Position of each id in `characters`
+    index: HashMap<String, usize>,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.characters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.characters.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.index.contains_key(id)
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Character> {
+        self.index.get(id).map(|&i| &self.characters[i])
+    }
+
+    pub(crate) fn get_mut(&mut self, id: &str) -> Option<&mut Character> {
+        self.index.get(id).map(|&i| &mut self.characters[i])
+    }
+
+    /// Two different participants at once, e.g. an attacker and their target
+    pub(crate) fn get_pair_mut(&mut self, a: &str, b: &str) -> Option<(&mut Character, &mut Character)> {
+        let (i, j) = (*self.index.get(a)?, *self.index.get(b)?);
+        if i == j {
+            return None;
+        }
+        let (low, high) = self.characters.split_at_mut(i.max(j));
+        let (first, second) = (&mut low[i.min(j)], &mut high[0]);
+        Some(if i < j { (first, second) } else { (second, first) })
+    }
+
+    /// Add a participant, replacing (and returning) any with the same id
+    pub fn insert(&mut self, character: Character) -> Option<Character> {
+        match self.index.get(&character.id) {
+            Some(&i) => Some(std::mem::replace(&mut self.characters[i], character)),
+            None => {
+                self.index.insert(character.id.clone(), self.characters.len());
+                self.characters.push(character);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Character> {
+        let i = self.index.remove(id)?;
+        let character = self.characters.remove(i);
+        for position in self.index.values_mut() {
+            if *position > i {
+                *position -= 1;
+            }
+        }
+        Some(character)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Character> {
+        self.characters.iter()
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> std::slice::IterMut<'_, Character> {
+        self.characters.iter_mut()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.characters.iter().map(|c| c.id.as_str())
+    }
+
+    pub fn alive(&self) -> impl Iterator<Item = &Character> {
+        self.characters.iter().filter(|c| c.is_alive())
+    }
+
+    pub fn dead(&self) -> impl Iterator<Item = &Character> {
+        self.characters.iter().filter(|c| !c.is_alive())
+    }
+
+    pub fn as_slice(&self) -> &[Character] {
+        &self.characters
+    }
+}
+
+impl From<Vec<Character>> for Roster {
+    fn from(characters: Vec<Character>) -> Self {
+        let mut roster = Self::new();
+        for character in characters {
+            roster.insert(character);
+        }
+        roster
+    }
+}
+
+impl From<Roster> for Vec<Character> {
+    fn from(roster: Roster) -> Self {
+        roster.characters
+    }
+}
+
+impl<'a> IntoIterator for &'a Roster {
+    type Item = &'a Character;
+    type IntoIter = std::slice::Iter<'a, Character>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::test_support::character_at;
+
+    #[test]
+    fn test_roster() {
+        let mut roster = Roster::new();
+        let ids: Vec<String> = ["Ana", "Bo", "Cy"]
+            .into_iter()
+            .map(|name| {
+                let c = character_at(name, 0, 0);
+                let id = c.id.clone();
+                roster.insert(c);
+                id
+            })
+            .collect();
+
+        // Both orders of a pair, and never the same participant twice
+        let (cy, ana) = roster.get_pair_mut(&ids[2], &ids[0]).unwrap();
+        assert_eq!((cy.name.as_str(), ana.name.as_str()), ("Cy", "Ana"));
+        ana.health.current = 0;
+        assert!(roster.get_pair_mut(&ids[1], &ids[1]).is_none());
+        assert!(roster.get_pair_mut(&ids[1], "nobody").is_none());
+
+        assert_eq!(roster.dead().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["Ana"]);
+        assert_eq!(roster.alive().count(), 2);
+
+        // Ids keep pointing at the right participant after a removal
+        assert_eq!(roster.remove(&ids[0]).unwrap().name, "Ana");
+        assert_eq!(roster.get(&ids[2]).unwrap().name, "Cy");
+        assert!(!roster.contains(&ids[0]));
+
+        let json = serde_json::to_string(&roster).unwrap();
+        let restored: Roster = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.ids().collect::<Vec<_>>(), roster.ids().collect::<Vec<_>>());
+        assert_eq!(restored.get(&ids[1]).unwrap().name, "Bo");
+    }
+}
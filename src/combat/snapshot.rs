@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+use serde::{Deserialize, Serialize};
+use crate::{Character, HexGrid, dice::RollerState};
+use super::{
+    Combat, CombatError, CombatLog, CombatOutcome, FriendlyFire, Roster, Teams, TurnBudget,
+    TurnOrder, VictoryCondition,
+};
+
+/// How many actions `Combat::undo` can step back through by default
+pub const UNDO_LIMIT: usize = 20;
+
+/// Everything needed to resume an encounter exactly where it was saved,
+/// down to the next die the roller will produce
+///
+/// Reactors are behaviour rather than state and are not saved; set them
+/// again on a combat rebuilt with `Combat::from_snapshot`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CombatSnapshot {
+    participants: Roster,
+    turn_order: TurnOrder,
+    roller: RollerState,
+    grid: Option<HexGrid>,
+    log: CombatLog,
+    teams: Teams,
+    friendly_fire: FriendlyFire,
+    victory_conditions: Vec<VictoryCondition>,
+    outcome: Option<CombatOutcome>,
+    reactions_used: HashSet<String>,
+    budget: Option<TurnBudget>,
+}
+
+/// State to return to if the action in progress is undone
+///
+/// Lighter than a snapshot: the grid is shared rather than copied, and the
+/// log is cut back to its old length instead of being stored.
+pub(super) struct Checkpoint {
+    participants: Roster,
+    turn_order: TurnOrder,
+    roller: RollerState,
+    grid: Option<Rc<HexGrid>>,
+    log_len: usize,
+    teams: Teams,
+    friendly_fire: FriendlyFire,
+    victory_conditions: Vec<VictoryCondition>,
+    outcome: Option<CombatOutcome>,
+    reactions_used: HashSet<String>,
+    budget: Option<TurnBudget>,
+}
+
+impl CombatSnapshot {
+    pub fn participants(&self) -> &[Character] {
+        self.participants.as_slice()
+    }
+
+    pub fn round(&self) -> u32 {
+        self.turn_order.round()
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+}
+
+impl Combat {
+    /// Save the whole encounter; fails if the dice roller cannot be saved
+    pub fn snapshot(&self) -> Result<CombatSnapshot, CombatError> {
+        Ok(CombatSnapshot {
+            participants: self.participants.clone(),
+            turn_order: self.turn_order.clone(),
+            roller: self.roller.state().ok_or(CombatError::NotSaveable)?,
+            grid: self.grid.as_deref().cloned(),
+            log: self.log.clone(),
+            teams: self.teams.clone(),
+            friendly_fire: self.friendly_fire,
+            victory_conditions: self.victory_conditions.clone(),
+            outcome: self.outcome.clone(),
+            reactions_used: self.reactions_used.clone(),
+            budget: self.budget.clone(),
+        })
+    }
+
+    /// Resume a saved encounter
+    pub fn from_snapshot(snapshot: CombatSnapshot) -> Self {
+        let mut combat = Self::with_roller(snapshot.roller.restore());
+        combat.load(snapshot);
+        combat
+    }
+
+    /// Return to a saved state, keeping the reactors already set
+    ///
+    /// Undo history is cleared, since it belongs to the abandoned timeline.
+    pub fn restore(&mut self, snapshot: CombatSnapshot) {
+        self.load(snapshot);
+        self.history.clear();
+    }
+
+    /// Step back to just before the last action, as if it never happened
+    ///
+    /// The roller is rewound too, so repeating the action rolls the same dice.
+    pub fn undo(&mut self) -> Result<(), CombatError> {
+        let checkpoint = self.history.pop().ok_or(CombatError::NothingToUndo)?;
+        self.participants = checkpoint.participants;
+        self.turn_order = checkpoint.turn_order;
+        self.roller = checkpoint.roller.restore();
+        self.grid = checkpoint.grid;
+        self.log.truncate(checkpoint.log_len);
+        self.teams = checkpoint.teams;
+        self.friendly_fire = checkpoint.friendly_fire;
+        self.victory_conditions = checkpoint.victory_conditions;
+        self.outcome = checkpoint.outcome;
+        self.reactions_used = checkpoint.reactions_used;
+        self.budget = checkpoint.budget;
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// How many actions to keep for `undo`; zero turns undo off, which saves
+    /// snapshotting every action in headless runs
+    pub fn set_undo_limit(&mut self, limit: usize) {
+        self.undo_limit = limit;
+        let excess = self.history.len().saturating_sub(limit);
+        self.history.drain(..excess);
+    }
+
+    fn load(&mut self, snapshot: CombatSnapshot) {
+        self.participants = snapshot.participants;
+        self.turn_order = snapshot.turn_order;
+        self.roller = snapshot.roller.restore();
+        self.grid = snapshot.grid.map(Rc::new);
+        self.log = snapshot.log;
+        self.teams = snapshot.teams;
+        self.friendly_fire = snapshot.friendly_fire;
+        self.victory_conditions = snapshot.victory_conditions;
+        self.outcome = snapshot.outcome;
+        self.reactions_used = snapshot.reactions_used;
+        self.budget = snapshot.budget;
+    }
+
+    /// Run an action that `undo` can take back if it changes anything
+    pub(super) fn undoable<T, E>(&mut self, action: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let checkpoint = self.begin_action();
+        let result = action(self);
+        self.end_action(checkpoint, result.is_ok());
+        result
+    }
+
+    /// Remember the state before an action; actions inside another share its checkpoint
+    pub(super) fn begin_action(&mut self) -> Option<Checkpoint> {
+        self.action_depth += 1;
+        if self.action_depth > 1 || self.undo_limit == 0 {
+            return None;
+        }
+        Some(Checkpoint {
+            participants: self.participants.clone(),
+            turn_order: self.turn_order.clone(),
+            roller: self.roller.state()?,
+            grid: self.grid.clone(),
+            log_len: self.log.len(),
+            teams: self.teams.clone(),
+            friendly_fire: self.friendly_fire,
+            victory_conditions: self.victory_conditions.clone(),
+            outcome: self.outcome.clone(),
+            reactions_used: self.reactions_used.clone(),
+            budget: self.budget.clone(),
+        })
+    }
+
+    /// Keep the checkpoint if the action succeeded or at least left a mark in the log
+    pub(super) fn end_action(&mut self, checkpoint: Option<Checkpoint>, succeeded: bool) {
+        self.action_depth -= 1;
+        let Some(checkpoint) = checkpoint else {
+            return;
+        };
+        if succeeded || self.log.len() != checkpoint.log_len {
+            self.history.push(checkpoint);
+            if self.history.len() > self.undo_limit {
+                self.history.remove(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        HexPosition, TerrainType,
+        combat::test_support::{character_at, open_grid},
+    };
+
+    fn character(name: &str, q: i32, r: i32) -> Character {
+        let mut character = character_at(name, q, r);
+        character.stats.strength = 14;
+        character.stats.dexterity = 12;
+        character
+    }
+
+    fn skirmish() -> Combat {
+        let mut combat = Combat::with_seed(11);
+        combat.set_grid(open_grid(6));
+        combat.add_participant(character("Knight", 1, 1));
+        combat.add_participant(character("Brigand", 2, 1));
+        combat
+    }
+
+    /// One turn each: attack and, if standing, hit back
+    fn exchange(combat: &mut Combat) {
+        for _ in 0..2 {
+            let Some(current) = combat.next_turn().map(|c| c.id.clone()) else {
+                return;
+            };
+            let target = combat.roster().ids().find(|id| *id != current).unwrap().to_string();
+            let _ = combat.attack(&current, &target);
+        }
+    }
+
+    #[test]
+    fn test_save_and_resume() {
+        let mut combat = skirmish();
+        exchange(&mut combat);
+
+        let saved = combat.snapshot().unwrap();
+        let mut from_json = Combat::from_snapshot(CombatSnapshot::from_json(&saved.to_json().unwrap()).unwrap());
+        let mut from_yaml = Combat::from_snapshot(CombatSnapshot::from_yaml(&saved.to_yaml().unwrap()).unwrap());
+        assert_eq!(from_json.round(), combat.round());
+        assert_eq!(from_json.grid().unwrap().get_cell(&HexPosition::new_2d(3, 3)).unwrap().terrain, TerrainType::Plain);
+
+        // The resumed fights play out exactly like the original
+        exchange(&mut combat);
+        exchange(&mut from_json);
+        exchange(&mut from_yaml);
+        assert_eq!(from_json.log(), combat.log());
+        assert_eq!(from_yaml.log(), combat.log());
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut combat = skirmish();
+        assert_eq!(combat.undo().unwrap_err(), CombatError::NothingToUndo);
+
+        let knight = combat.participants()[0].id.clone();
+        let brigand = combat.participants()[1].id.clone();
+        while combat.next_turn().map(|c| c.id.clone()) != Some(knight.clone()) {}
+
+        combat.move_participant(&knight, HexPosition::new_2d(1, 3)).unwrap();
+        let moved = combat.participants()[0].movement.remaining;
+        let before = combat.get_participant(&brigand).unwrap().health.current;
+        combat.move_participant(&knight, HexPosition::new_2d(2, 2)).unwrap();
+        let logged = combat.log().len();
+        let first = combat.attack(&knight, &brigand).unwrap();
+
+        combat.undo().unwrap();
+        assert_eq!(combat.log().len(), logged);
+        assert_eq!(combat.get_participant(&brigand).unwrap().health.current, before);
+        assert!(combat.can_take(&knight, crate::combat::ActionKind::Action));
+
+        // Undoing rewinds the dice as well, so the retry rolls the same
+        let retry = combat.attack(&knight, &brigand).unwrap();
+        assert_eq!(retry.attack_roll, first.attack_roll);
+
+        // A failed action leaves nothing to undo; the moves before it are undone in turn
+        assert!(combat.move_participant(&knight, HexPosition::new_2d(2, 1)).is_err());
+        combat.undo().unwrap();
+        combat.undo().unwrap();
+        assert_eq!(combat.participants()[0].position, HexPosition::new_2d(1, 3));
+        assert_eq!(combat.participants()[0].movement.remaining, moved);
+    }
+}
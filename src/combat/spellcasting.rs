@@ -0,0 +1,402 @@
+use crate::{
+    HexPosition,
+    character::Ability,
+    check::{Check, SaveOutcome},
+    dice::{RollMode, RollResult},
+    spell::{Concentration, Spell, SpellResolution},
+};
+use super::{ActionKind, Combat, CombatError, CombatEvent, FriendlyFire, Reaction, ReactionTrigger};
+
+/// What came of casting a spell
+#[derive(Debug, Clone, Default)]
+pub struct SpellOutcome {
+    /// The participant whose reaction stopped the spell, if one did
+    pub countered_by: Option<String>,
+    pub hits: Vec<SpellHit>,
+}
+
+/// How one target fared against a spell
+#[derive(Debug, Clone)]
+pub struct SpellHit {
+    pub target_id: String,
+    pub attack_roll: Option<RollResult>,
+    pub save_roll: Option<RollResult>,
+    /// The attack hit, the save failed, or the spell needed neither
+    pub landed: bool,
+    pub damage: i32,
+    pub healed: i32,
+}
+
+impl Combat {
+    /// Cast `spell` from `caster_id` at the hex `target`
+    ///
+    /// The target must be within the spell's range and in the caster's line of
+    /// sight. Area spells affect everyone in their shape that the target hex
+    /// can see; other spells affect whoever stands on it. Harmful spells spare
+    /// the caster's allies unless friendly fire is allowed, and helpful ones
+    /// only touch allies. Takes the caster's action and a slot or mana, even
+    /// if an enemy counters the spell.
+    pub fn cast_spell(
+        &mut self,
+        caster_id: &str,
+        spell: &Spell,
+        target: HexPosition,
+    ) -> Result<SpellOutcome, CombatError> {
+        self.undoable(|combat| combat.resolve_spell(caster_id, spell, target))
+    }
+
+    fn resolve_spell(
+        &mut self,
+        caster_id: &str,
+        spell: &Spell,
+        target: HexPosition,
+    ) -> Result<SpellOutcome, CombatError> {
+        if self.is_over() {
+            return Err(CombatError::EncounterOver);
+        }
+        let caster = self.get_participant(caster_id).ok_or(CombatError::ParticipantNotFound)?;
+        if !caster.is_alive() {
+            return Err(CombatError::AttackerDown);
+        }
+        if !caster.can_act() {
+            return Err(CombatError::Incapacitated);
+        }
+        caster.can_cast(spell)?;
+        let distance = caster.position.distance(&target);
+        if distance > spell.range {
+            return Err(CombatError::OutOfRange { distance, range: spell.range });
+        }
+        let sees = |from: HexPosition, to: HexPosition| {
+            self.grid.as_ref().is_none_or(|grid| grid.has_line_of_sight(from, to))
+        };
+        if !sees(caster.position, target) {
+            return Err(CombatError::NoLineOfSight);
+        }
+        let dc = caster.spell_save_dc().unwrap_or_default();
+        let attack_bonus = caster.spell_attack_bonus().unwrap_or_default();
+
+        let harmful = spell.is_harmful();
+        let spare_allies = harmful && self.friendly_fire == FriendlyFire::Prevented;
+        let affects = |id: &str| {
+            if harmful {
+                !(spare_allies && self.is_allied(caster_id, id))
+            } else {
+                self.is_allied(caster_id, id)
+            }
+        };
+        let targets: Vec<String> = match &spell.area {
+            Some(shape) => self
+                .participants
+                .alive()
+                .filter(|c| shape.contains(target, &c.position))
+                .filter(|c| sees(target, c.position))
+                .filter(|c| affects(&c.id))
+                .map(|c| c.id.clone())
+                .collect(),
+            None => {
+                let occupant = self
+                    .participants
+                    .alive()
+                    .find(|c| c.position.q == target.q && c.position.r == target.r)
+                    .ok_or(CombatError::NoSpellTarget)?;
+                if spare_allies && self.is_allied(caster_id, &occupant.id) {
+                    return Err(CombatError::FriendlyFire);
+                }
+                if !affects(&occupant.id) {
+                    return Err(CombatError::NoSpellTarget);
+                }
+                vec![occupant.id.clone()]
+            }
+        };
+
+        self.turn_budget(caster_id)?.spend(ActionKind::Action)?;
+        let caster = self.participants.get_mut(caster_id).ok_or(CombatError::ParticipantNotFound)?;
+        caster.spend_spell(spell)?;
+        self.record(CombatEvent::SpellCast {
+            caster_id: caster_id.to_string(),
+            spell: spell.name.clone(),
+            target,
+        });
+        // Starting a new concentration spell drops the old one, countered or not
+        if spell.concentration {
+            self.break_concentration(caster_id);
+        }
+
+        let trigger = ReactionTrigger::SpellCast { caster_id: caster_id.to_string(), spell: spell.name.clone() };
+        let countered_by = self
+            .trigger_reactions(&trigger)
+            .into_iter()
+            .find(|(_, reaction)| *reaction == Reaction::Counter)
+            .map(|(id, _)| id);
+        if countered_by.is_some() {
+            return Ok(SpellOutcome { countered_by, hits: Vec::new() });
+        }
+
+        let damage = spell.damage.as_ref().map_or(0, |d| d.evaluate(self.roller.as_mut()).total.max(0));
+        let healing = spell.healing.as_ref().map_or(0, |h| h.evaluate(self.roller.as_mut()).total.max(0));
+        let mut hits = Vec::new();
+        for id in targets {
+            if !self.participants.contains(&id) {
+                continue;
+            }
+            let (attack_roll, save_roll, landed, raw_damage) = match spell.resolution {
+                SpellResolution::Automatic => (None, None, true, damage),
+                SpellResolution::Attack => {
+                    let attack = self.roll_attack(caster_id, &id, attack_bonus, RollMode::Normal)?;
+                    let raw_damage = if attack.critical { damage * 2 } else { damage };
+                    let raw_damage = if attack.hit { (raw_damage - attack.reduction).max(0) } else { 0 };
+                    (Some(attack.roll), None, attack.hit, raw_damage)
+                }
+                SpellResolution::Save { ability, on_success } => {
+                    let target = self.participants.get(&id).ok_or(CombatError::ParticipantNotFound)?;
+                    let save = target.attempt(Check::Save(ability), dc, RollMode::Normal, self.roller.as_mut());
+                    let raw_damage = match (save.success, on_success) {
+                        (true, SaveOutcome::HalfDamage) => damage / 2,
+                        (true, SaveOutcome::NoDamage) => 0,
+                        (false, _) => damage,
+                    };
+                    (None, Some(save.roll), !save.success, raw_damage)
+                }
+            };
+
+            let target = self.participants.get_mut(&id).ok_or(CombatError::ParticipantNotFound)?;
+            let dealt = target.take_damage(raw_damage, spell.damage_type);
+            let healed = if landed && target.is_alive() { target.heal(healing) } else { 0 };
+            if let (true, Some(effect)) = (landed, &spell.effect) {
+                let mut effect = effect.clone();
+                effect.source = Some(caster_id.to_string());
+                target.add_effect(effect);
+            }
+            let died = !target.is_alive();
+
+            self.record(CombatEvent::SpellHit {
+                id: id.clone(),
+                attack_roll: attack_roll.clone(),
+                save_roll: save_roll.clone(),
+                landed,
+                damage: dealt,
+                healed,
+            });
+            if died {
+                self.record(CombatEvent::Died { id: id.clone() });
+            }
+            self.maintain_concentration(&id, dealt);
+            hits.push(SpellHit { target_id: id, attack_roll, save_roll, landed, damage: dealt, healed });
+        }
+
+        let caster = self.participants.get_mut(caster_id).ok_or(CombatError::ParticipantNotFound)?;
+        if let (true, Some(spellcasting)) = (spell.concentration && caster.can_act(), caster.spellcasting.as_mut()) {
+            spellcasting.concentration = Some(Concentration {
+                spell: spell.name.clone(),
+                effect: spell.effect.as_ref().map(|e| e.kind.clone()),
+            });
+        }
+        self.check_victory();
+        Ok(SpellOutcome { countered_by: None, hits })
+    }
+
+    /// After `id` takes `damage`, keep their concentration only if they can
+    /// still act and make a constitution save (DC 10, or half the damage if higher)
+    pub(super) fn maintain_concentration(&mut self, id: &str, damage: i32) {
+        let Some(character) = self.participants.get(id) else {
+            return;
+        };
+        if character.spellcasting.as_ref().is_none_or(|s| s.concentration.is_none()) {
+            return;
+        }
+        let keeps = character.can_act()
+            && (damage <= 0 || {
+                let dc = (damage / 2).max(10);
+                character.attempt(Check::Save(Ability::Constitution), dc, RollMode::Normal, self.roller.as_mut()).success
+            });
+        if !keeps {
+            self.break_concentration(id);
+        }
+    }
+
+    /// End the spell `id` is concentrating on, lifting its effect from everyone it touched
+    /// unless another caster's application of the same effect merged into it
+    pub fn break_concentration(&mut self, id: &str) {
+        let Some(concentration) = self
+            .participants
+            .get_mut(id)
+            .and_then(|c| c.spellcasting.as_mut())
+            .and_then(|s| s.concentration.take())
+        else {
+            return;
+        };
+        if let Some(kind) = &concentration.effect {
+            for participant in self.participants.iter_mut() {
+                let effect = participant.effects.iter_mut().find(|e| e.kind == *kind);
+                if effect.is_some_and(|e| e.drop_source(id)) {
+                    participant.remove_effect(kind);
+                }
+            }
+        }
+        self.record(CombatEvent::ConcentrationBroken { id: id.to_string(), spell: concentration.spell });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Character,
+        class::ClassRegistry,
+        combat::{Counterspell, ShieldBlock, test_support::{character_at, open_grid}},
+        dice::ScriptedRoller,
+        effect::{EffectKind, StatusEffect},
+        spell::{CastingResource, SpellBook, SpellError},
+    };
+
+    fn character(name: &str, q: i32, r: i32) -> Character {
+        let mut character = character_at(name, q, r);
+        character.stats.intelligence = 16;
+        character
+    }
+
+    fn wizard(level: i32) -> Character {
+        let registry = ClassRegistry::with_defaults();
+        let mut wizard = character("Wizard", 0, 0);
+        wizard.start_class("Wizard", &registry).unwrap();
+        wizard.gain_experience(crate::class::experience_for_level(level));
+        for _ in 1..level {
+            wizard.level_up("Wizard", &registry, &mut ScriptedRoller::new([4])).unwrap();
+        }
+        wizard
+    }
+
+    #[test]
+    fn test_fireball_spends_a_slot() {
+        let book = SpellBook::with_defaults();
+        let fireball = book.get("Fireball").unwrap();
+
+        // Initiative for three, 8d6 totalling 24, then a failed and a made save against DC 14
+        let faces = [20, 10, 10, 3, 3, 3, 3, 3, 3, 3, 3, 4, 17];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        combat.set_grid(open_grid(10));
+        combat.add_participant(wizard(5));
+        combat.add_participant(character("Orc", 5, 5));
+        combat.add_participant(character("Goblin", 6, 5));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+
+        let far = HexPosition::new_2d(40, 0);
+        assert_eq!(
+            combat.cast_spell(&ids[0], fireball, far).unwrap_err(),
+            CombatError::OutOfRange { distance: 40, range: 30 }
+        );
+
+        let outcome = combat.cast_spell(&ids[0], fireball, HexPosition::new_2d(5, 4)).unwrap();
+        let hits: Vec<(bool, i32)> = outcome.hits.iter().map(|h| (h.landed, h.damage)).collect();
+        assert_eq!(hits, vec![(true, 24), (false, 12)]);
+        let caster = combat.get_participant(&ids[0]).unwrap();
+        let Some(CastingResource::Slots { current, .. }) = caster.spellcasting.as_ref().map(|s| &s.resource) else {
+            panic!("wizards cast with slots");
+        };
+        assert_eq!(current[..3], [4, 3, 1]);
+    }
+
+    #[test]
+    fn test_spell_attack_uses_casters_mode() {
+        let book = SpellBook::with_defaults();
+        let fire_bolt = book.get("Fire Bolt").unwrap();
+
+        // Initiative, 1d10 damage, then an 18 and a 4 rolled with disadvantage
+        let faces = [20, 10, 6, 18, 4];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        let mut caster = wizard(1);
+        caster.add_effect(StatusEffect::poisoned(1));
+        combat.add_participant(caster);
+        combat.add_participant(character("Orc", 2, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+
+        let outcome = combat.cast_spell(&ids[0], fire_bolt, HexPosition::new_2d(2, 0)).unwrap();
+        let roll = outcome.hits[0].attack_roll.as_ref().unwrap();
+        assert_eq!((roll.mode, roll.natural()), (RollMode::Disadvantage, 4));
+        assert!(!outcome.hits[0].landed);
+    }
+
+    #[test]
+    fn test_spell_attack_bonus_dice_and_reactions() {
+        let book = SpellBook::with_defaults();
+        let fire_bolt = book.get("Fire Bolt").unwrap();
+
+        // Initiative, 1d10 damage, a 5 on the d20 and a 4 from bless: 5 + 5 + 4 hits AC 10
+        let faces = [20, 10, 6, 5, 4];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        let mut caster = wizard(1);
+        caster.add_effect(StatusEffect::blessed(1));
+        combat.add_participant(caster);
+        combat.add_participant(character("Orc", 2, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.set_reactor(&ids[1], Box::new(ShieldBlock { bonus: 5 }));
+        combat.next_turn();
+
+        // ...until the shield raises it to 15
+        let outcome = combat.cast_spell(&ids[0], fire_bolt, HexPosition::new_2d(2, 0)).unwrap();
+        assert_eq!(outcome.hits[0].attack_roll.as_ref().unwrap().value, 14);
+        assert!(!outcome.hits[0].landed);
+        assert!(!combat.has_reaction(&ids[1]));
+        assert_eq!(combat.get_participant(&ids[1]).unwrap().health.current, 20);
+    }
+
+    #[test]
+    fn test_concentration_and_counterspell() {
+        let book = SpellBook::with_defaults();
+        let hold = book.get("Hold Person").unwrap();
+
+        // Initiative, then a failed wisdom save against hold person
+        let faces = [20, 10, 10, 2];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        combat.add_participant(wizard(3));
+        combat.add_participant(character("Knight", 2, 0));
+        combat.add_participant(character("Mage", 3, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+
+        let outcome = combat.cast_spell(&ids[0], hold, HexPosition::new_2d(2, 0)).unwrap();
+        assert!(outcome.hits[0].landed);
+        let held = EffectKind::Custom("Held".to_string());
+        assert!(!combat.get_participant(&ids[1]).unwrap().can_act());
+
+        combat.break_concentration(&ids[0]);
+        assert!(!combat.get_participant(&ids[1]).unwrap().has_effect(&held));
+
+        // Next round the enemy mage counters; the slot is spent all the same
+        combat.set_reactor(&ids[2], Box::new(Counterspell));
+        combat.next_turn();
+        combat.next_turn();
+        combat.next_turn();
+        let outcome = combat.cast_spell(&ids[0], hold, HexPosition::new_2d(2, 0)).unwrap();
+        assert_eq!(outcome.countered_by.as_deref(), Some(ids[2].as_str()));
+        assert!(combat.get_participant(&ids[1]).unwrap().can_act());
+        let caster = combat.get_participant(&ids[0]).unwrap();
+        assert_eq!(caster.can_cast(hold), Err(SpellError::NoSlot { level: 2 }));
+    }
+
+    #[test]
+    fn test_shared_concentration_effect() {
+        let book = SpellBook::with_defaults();
+        let hold = book.get("Hold Person").unwrap();
+
+        // Initiative, then the knight fails both wisdom saves
+        let faces = [20, 15, 10, 2, 2];
+        let mut combat = Combat::with_roller(Box::new(ScriptedRoller::new(faces)));
+        combat.add_participant(wizard(3));
+        combat.add_participant(wizard(3));
+        combat.add_participant(character("Knight", 2, 0));
+        let ids: Vec<String> = combat.participants().iter().map(|c| c.id.clone()).collect();
+        combat.next_turn();
+        combat.cast_spell(&ids[0], hold, HexPosition::new_2d(2, 0)).unwrap();
+        combat.next_turn();
+        combat.cast_spell(&ids[1], hold, HexPosition::new_2d(2, 0)).unwrap();
+
+        // The first wizard letting go leaves the second one's hold in place
+        combat.break_concentration(&ids[0]);
+        assert!(!combat.get_participant(&ids[2]).unwrap().can_act());
+        combat.break_concentration(&ids[1]);
+        assert!(combat.get_participant(&ids[2]).unwrap().can_act());
+    }
+}
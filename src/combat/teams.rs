@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::HexPosition;
+
+/// How two teams regard each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Relation {
+    Allied,
+    /// Not targeted by AI, but not protected from friendly-fire rules either
+    Neutral,
+    Hostile,
+}
+
+/// Whether attacks may target allies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FriendlyFire {
+    Allowed,
+    #[default]
+    Prevented,
+}
+
+/// Team membership of participants and the relations between teams
+///
+/// Teams are hostile to each other unless told otherwise, and a participant
+/// without a team is hostile to everyone.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Teams {
+    members: HashMap<String, String>,
+    relations: HashMap<String, HashMap<String, Relation>>,
+}
+
+impl Teams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_team(&mut self, id: &str, team: &str) {
+        self.members.insert(id.to_string(), team.to_string());
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<String> {
+        self.members.remove(id)
+    }
+
+    pub fn team(&self, id: &str) -> Option<&str> {
+        self.members.get(id).map(String::as_str)
+    }
+
+    /// Ids of the participants on a team
+    pub fn members<'a>(&'a self, team: &'a str) -> impl Iterator<Item = &'a str> {
+        self.members
+            .iter()
+            .filter(move |(_, t)| t.as_str() == team)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// Set how two teams regard each other, in both directions
+    pub fn set_relation(&mut self, a: &str, b: &str, relation: Relation) {
+        self.relations.entry(a.to_string()).or_default().insert(b.to_string(), relation);
+        self.relations.entry(b.to_string()).or_default().insert(a.to_string(), relation);
+    }
+
+    pub fn relation(&self, a: &str, b: &str) -> Relation {
+        if a == b {
+            return Relation::Allied;
+        }
+        self.relations
+            .get(a)
+            .and_then(|r| r.get(b))
+            .copied()
+            .unwrap_or(Relation::Hostile)
+    }
+
+    /// Relation between two participants by id
+    pub fn relation_between(&self, a: &str, b: &str) -> Relation {
+        if a == b {
+            return Relation::Allied;
+        }
+        match (self.team(a), self.team(b)) {
+            (Some(x), Some(y)) => self.relation(x, y),
+            _ => Relation::Hostile,
+        }
+    }
+
+    /// The side a participant fights for: its team, or its own id without one
+    pub fn side<'a>(&'a self, id: &'a str) -> &'a str {
+        self.team(id).unwrap_or(id)
+    }
+}
+
+/// A way for an encounter to end
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VictoryCondition {
+    /// Ends once no two living participants are hostile; the survivors' sides win
+    LastTeamStanding,
+    /// `team` loses as soon as `target_id` dies or leaves; teams hostile to it win
+    Protect { team: String, target_id: String },
+    /// `team` wins if any of its members are still alive after `rounds` full rounds
+    SurviveRounds { team: String, rounds: u32 },
+    /// `team` wins as soon as one of its living members stands on `hex`
+    ReachHex { team: String, hex: HexPosition },
+}
+
+/// How an encounter ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombatOutcome {
+    /// Winning sides (team names, or ids of participants without a team);
+    /// empty when nobody is left standing
+    pub winners: Vec<String>,
+    pub condition: VictoryCondition,
+    pub round: u32,
+}
+
+impl fmt::Display for VictoryCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VictoryCondition::LastTeamStanding => write!(f, "last team standing"),
+            VictoryCondition::Protect { team, .. } => write!(f, "{} failed to protect their charge", team),
+            VictoryCondition::SurviveRounds { team, rounds } => write!(f, "{} survived {} rounds", team, rounds),
+            VictoryCondition::ReachHex { team, hex } => {
+                write!(f, "{} reached ({}, {})", team, hex.q, hex.r)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relations() {
+        let mut teams = Teams::new();
+        teams.set_team("a", "red");
+        teams.set_team("b", "red");
+        teams.set_team("c", "blue");
+        teams.set_team("d", "green");
+        teams.set_relation("red", "green", Relation::Neutral);
+
+        assert_eq!(teams.relation_between("a", "b"), Relation::Allied);
+        assert_eq!(teams.relation_between("a", "c"), Relation::Hostile);
+        assert_eq!(teams.relation_between("d", "a"), Relation::Neutral);
+        assert_eq!(teams.relation_between("a", "loner"), Relation::Hostile);
+        assert_eq!(teams.side("loner"), "loner");
+
+        let mut red: Vec<&str> = teams.members("red").collect();
+        red.sort_unstable();
+        assert_eq!(red, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,21 @@
+//! Fixtures shared by the combat tests
+
+use crate::{Character, HexGrid, HexPosition, TerrainType, character::CharacterStats, item::RaceType};
+
+/// A human with 10 in every ability, standing at `(q, r)`
+pub(crate) fn character_at(name: &str, q: i32, r: i32) -> Character {
+    let mut character = Character::new(name.to_string(), RaceType::Human, CharacterStats::from_scores([10; 6]));
+    character.position = HexPosition::new_2d(q, r);
+    character
+}
+
+/// A `size` by `size` grid of level plain
+pub(crate) fn open_grid(size: i32) -> HexGrid {
+    let mut grid = HexGrid::with_size(size, size);
+    for q in 0..size {
+        for r in 0..size {
+            grid.add_cell(HexPosition::new_2d(q, r), TerrainType::Plain, 0);
+        }
+    }
+    grid
+}
@@ -1,27 +1,205 @@
-use rand::Rng;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+mod distribution;
+mod expr;
+mod roller;
+
+pub use distribution::{Distribution, DistributionError};
+pub use expr::{
+    DiceTerm, DieRoll, Evaluation, Expr, Keep, ParseError, ParseErrorKind, TermRoll,
+    MAX_DICE, MAX_EXPLOSIONS, MAX_SIDES,
+};
+pub use roller::{DiceRoller, RollerState, ScriptedRoller, SeededRoller, ThreadRoller};
 
 /// Represents the result of a dice roll
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RollResult {
     pub value: i32,
     pub dice_count: i32,
     pub dice_type: i32,
     pub modifier: i32,
+    /// Every die rolled, including those dropped by advantage or rerolls
+    pub dice: Vec<DieRoll>,
+    pub mode: RollMode,
+    /// Every kept die shows its highest face (a "natural 20" on a d20)
+    pub natural_max: bool,
+    /// Every kept die shows a 1
+    pub natural_min: bool,
+}
+
+/// Whether a roll is made once, or twice keeping the better or worse result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RollMode {
+    #[default]
+    Normal,
+    Advantage,
+    Disadvantage,
+}
+
+/// Which low faces are rerolled (once, keeping the new face)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RerollPolicy {
+    #[default]
+    Never,
+    Ones,
+    AtOrBelow(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RollOptions {
+    pub mode: RollMode,
+    pub reroll: RerollPolicy,
+}
+
+impl RollMode {
+    /// Combine two sources of advantage/disadvantage; opposing sources cancel out
+    pub fn combine(self, other: RollMode) -> RollMode {
+        match (self, other) {
+            (RollMode::Normal, mode) | (mode, RollMode::Normal) => mode,
+            (a, b) if a == b => a,
+            _ => RollMode::Normal,
+        }
+    }
+
+    /// Resolve any number of sources: advantage and disadvantage cancel
+    /// entirely if both are present, however many of each there are
+    pub fn from_sources(modes: impl IntoIterator<Item = RollMode>) -> RollMode {
+        let (mut advantage, mut disadvantage) = (false, false);
+        for mode in modes {
+            match mode {
+                RollMode::Advantage => advantage = true,
+                RollMode::Disadvantage => disadvantage = true,
+                RollMode::Normal => {}
+            }
+        }
+        match (advantage, disadvantage) {
+            (true, false) => RollMode::Advantage,
+            (false, true) => RollMode::Disadvantage,
+            _ => RollMode::Normal,
+        }
+    }
+}
+
+impl RerollPolicy {
+    fn threshold(&self) -> i32 {
+        match self {
+            RerollPolicy::Never => 0,
+            RerollPolicy::Ones => 1,
+            RerollPolicy::AtOrBelow(face) => *face,
+        }
+    }
+}
+
+impl RollResult {
+    /// Sum of the kept dice, without the modifier
+    pub fn natural(&self) -> i32 {
+        self.value - self.modifier
+    }
+
+    pub fn kept_dice(&self) -> impl Iterator<Item = &DieRoll> {
+        self.dice.iter().filter(|d| d.kept)
+    }
+
+    pub fn dropped_dice(&self) -> impl Iterator<Item = &DieRoll> {
+        self.dice.iter().filter(|d| !d.kept)
+    }
+}
+
+impl fmt::Display for RollResult {
+    /// Renders as e.g. "1d20 [20, (7)] + 3 = 23", dropped dice in parentheses
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{} [", self.dice_count, self.dice_type)?;
+        for (i, die) in self.dice.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if die.kept {
+                write!(f, "{}", die.face)?;
+            } else {
+                write!(f, "({})", die.face)?;
+            }
+        }
+        write!(f, "]")?;
+        match self.modifier {
+            0 => {}
+            m if m > 0 => write!(f, " + {}", m)?,
+            m => write!(f, " - {}", -m)?,
+        }
+        write!(f, " = {}", self.value)
+    }
 }
 
 /// Roll dice in standard RPG notation (e.g., "2d6+3")
-pub fn roll(dice_count: i32, dice_type: i32, modifier: i32) -> RollResult {
-    let mut rng = rand::thread_rng();
-    let value: i32 = (0..dice_count)
-        .map(|_| rng.gen_range(1..=dice_type))
-        .sum::<i32>() + modifier;
+pub fn roll(roller: &mut dyn DiceRoller, dice_count: i32, dice_type: i32, modifier: i32) -> RollResult {
+    roll_with(roller, dice_count, dice_type, modifier, RollOptions::default())
+}
+
+/// Roll dice with advantage/disadvantage and a reroll policy applied
+pub fn roll_with(
+    roller: &mut dyn DiceRoller,
+    dice_count: i32,
+    dice_type: i32,
+    modifier: i32,
+    options: RollOptions,
+) -> RollResult {
+    let mut dice = roll_set(roller, dice_count, dice_type, options.reroll);
+
+    if options.mode != RollMode::Normal {
+        // Roll the whole set again and keep whichever set is better (or worse)
+        let mut second = roll_set(roller, dice_count, dice_type, options.reroll);
+        let first_total = kept_total(&dice);
+        let second_total = kept_total(&second);
+        let keep_first = match options.mode {
+            RollMode::Advantage => first_total >= second_total,
+            _ => first_total <= second_total,
+        };
+        let dropped = if keep_first { &mut second } else { &mut dice };
+        for die in dropped.iter_mut() {
+            die.kept = false;
+        }
+        dice.append(&mut second);
+    }
+
+    let kept: Vec<&DieRoll> = dice.iter().filter(|d| d.kept).collect();
+    let natural_max = !kept.is_empty() && kept.iter().all(|d| d.face == d.sides);
+    let natural_min = !kept.is_empty() && kept.iter().all(|d| d.face == 1);
 
     RollResult {
-        value,
+        value: kept_total(&dice) + modifier,
         dice_count,
         dice_type,
         modifier,
+        dice,
+        mode: options.mode,
+        natural_max,
+        natural_min,
+    }
+}
+
+fn roll_set(roller: &mut dyn DiceRoller, dice_count: i32, dice_type: i32, reroll: RerollPolicy) -> Vec<DieRoll> {
+    let threshold = reroll.threshold();
+    let mut dice = Vec::with_capacity(dice_count as usize);
+    for _ in 0..dice_count {
+        let face = roller.roll_die(dice_type);
+        if face <= threshold {
+            // The original face is kept in the record but no longer counts
+            dice.push(DieRoll { face, sides: dice_type, kept: false, exploded: false });
+            dice.push(DieRoll { face: roller.roll_die(dice_type), sides: dice_type, kept: true, exploded: false });
+        } else {
+            dice.push(DieRoll { face, sides: dice_type, kept: true, exploded: false });
+        }
     }
+    dice
+}
+
+fn kept_total(dice: &[DieRoll]) -> i32 {
+    dice.iter().filter(|d| d.kept).map(|d| d.face).sum()
+}
+
+/// Parse a dice expression such as "4d6kh3 + 1d8 - 2"
+pub fn parse(notation: &str) -> Result<Expr, ParseError> {
+    Expr::parse(notation)
 }
 
 #[cfg(test)]
@@ -30,7 +208,35 @@ mod tests {
 
     #[test]
     fn test_roll_in_range() {
-        let result = roll(2, 6, 0);
+        let result = roll(&mut ThreadRoller, 2, 6, 0);
         assert!(result.value >= 2 && result.value <= 12);
     }
+
+    #[test]
+    fn test_roll_advantage_keeps_faces() {
+        let mut roller = ScriptedRoller::new([7, 20]);
+        let options = RollOptions { mode: RollMode::Advantage, ..Default::default() };
+        let result = roll_with(&mut roller, 1, 20, 3, options);
+        assert_eq!(result.value, 23);
+        assert_eq!(result.natural(), 20);
+        assert!(result.natural_max);
+        assert_eq!(result.dropped_dice().map(|d| d.face).collect::<Vec<_>>(), vec![7]);
+
+        let mut roller = ScriptedRoller::new([7, 20]);
+        let options = RollOptions { mode: RollMode::Disadvantage, ..Default::default() };
+        let result = roll_with(&mut roller, 1, 20, 3, options);
+        assert_eq!(result.value, 10);
+        assert!(!result.natural_max);
+    }
+
+    #[test]
+    fn test_reroll_ones() {
+        let mut roller = ScriptedRoller::new([1, 1, 5]);
+        let options = RollOptions { reroll: RerollPolicy::Ones, ..Default::default() };
+        let result = roll_with(&mut roller, 2, 6, 0, options);
+        // The first die is rerolled once and its second 1 stands
+        assert_eq!(result.value, 6);
+        assert_eq!(result.dice.len(), 3);
+        assert_eq!(result.kept_dice().count(), 2);
+    }
 }
@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use super::{DiceTerm, Expr, Keep, MAX_EXPLOSIONS};
+
+/// Rough cap on the number of DP steps a single term may take
+const MAX_WORK: f64 = 5.0e7;
+/// Exploding chains are cut once their probability falls below this
+const EXPLOSION_EPSILON: f64 = 1.0e-15;
+
+/// Exact probability mass function of a dice expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    pmf: BTreeMap<i32, f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DistributionError {
+    /// The term is too large to enumerate exactly
+    TooComplex(DiceTerm),
+}
+
+impl fmt::Display for DistributionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistributionError::TooComplex(term) => {
+                write!(f, "{} is too large to compute an exact distribution", term)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DistributionError {}
+
+impl Distribution {
+    pub fn constant(value: i32) -> Self {
+        let mut pmf = BTreeMap::new();
+        pmf.insert(value, 1.0);
+        Self { pmf }
+    }
+
+    /// A single fair die with faces `1..=sides`
+    pub fn die(sides: i32) -> Self {
+        let p = 1.0 / sides as f64;
+        Self {
+            pmf: (1..=sides).map(|face| (face, p)).collect(),
+        }
+    }
+
+    /// Sum of two independent distributions
+    pub fn add(&self, other: &Distribution) -> Distribution {
+        let mut pmf = BTreeMap::new();
+        for (a, pa) in &self.pmf {
+            for (b, pb) in &other.pmf {
+                *pmf.entry(a + b).or_insert(0.0) += pa * pb;
+            }
+        }
+        Distribution { pmf }
+    }
+
+    pub fn negate(&self) -> Distribution {
+        Distribution {
+            pmf: self.pmf.iter().map(|(v, p)| (-v, *p)).collect(),
+        }
+    }
+
+    pub fn subtract(&self, other: &Distribution) -> Distribution {
+        self.add(&other.negate())
+    }
+
+    /// Distribution of the best (or worst) of two independent draws
+    pub fn best_of_two(&self, highest: bool) -> Distribution {
+        let mut pmf = BTreeMap::new();
+        for (a, pa) in &self.pmf {
+            for (b, pb) in &self.pmf {
+                let value = if highest { *a.max(b) } else { *a.min(b) };
+                *pmf.entry(value).or_insert(0.0) += pa * pb;
+            }
+        }
+        Distribution { pmf }
+    }
+
+    /// Probability of exactly `value`
+    pub fn probability(&self, value: i32) -> f64 {
+        self.pmf.get(&value).copied().unwrap_or(0.0)
+    }
+
+    /// Probability that the result is greater than or equal to `target`
+    pub fn at_least(&self, target: i32) -> f64 {
+        self.pmf.range(target..).map(|(_, p)| p).sum()
+    }
+
+    /// Probability that the result is less than or equal to `target`
+    pub fn at_most(&self, target: i32) -> f64 {
+        self.pmf.range(..=target).map(|(_, p)| p).sum()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.pmf.iter().map(|(v, p)| *v as f64 * p).sum()
+    }
+
+    pub fn variance(&self) -> f64 {
+        let mean = self.mean();
+        self.pmf
+            .iter()
+            .map(|(v, p)| (*v as f64 - mean).powi(2) * p)
+            .sum()
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    pub fn min(&self) -> i32 {
+        *self.pmf.keys().next().expect("distribution is never empty")
+    }
+
+    pub fn max(&self) -> i32 {
+        *self.pmf.keys().next_back().expect("distribution is never empty")
+    }
+
+    /// Outcomes and their probabilities in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (i32, f64)> + '_ {
+        self.pmf.iter().map(|(v, p)| (*v, *p))
+    }
+}
+
+impl Expr {
+    /// Compute the exact probability distribution of this expression
+    ///
+    /// Exploding dice are infinite in principle; their tails are cut once the
+    /// remaining probability drops below floating point precision.
+    pub fn distribution(&self) -> Result<Distribution, DistributionError> {
+        match self {
+            Expr::Constant(value) => Ok(Distribution::constant(*value)),
+            Expr::Dice(term) => term.distribution(),
+            Expr::Negate(inner) => Ok(inner.distribution()?.negate()),
+            Expr::Add(lhs, rhs) => Ok(lhs.distribution()?.add(&rhs.distribution()?)),
+            Expr::Subtract(lhs, rhs) => Ok(lhs.distribution()?.subtract(&rhs.distribution()?)),
+        }
+    }
+}
+
+impl DiceTerm {
+    pub fn distribution(&self) -> Result<Distribution, DistributionError> {
+        let count = self.count as f64;
+        let sides = self.sides as f64;
+
+        if self.explode {
+            let single = exploding_die(self.sides);
+            let span = (single.max() - single.min() + 1) as f64;
+            if count * count * span * span > MAX_WORK {
+                return Err(DistributionError::TooComplex(*self));
+            }
+            return Ok(sum_of(&single, self.count));
+        }
+
+        match self.keep {
+            None => {
+                if count * count * sides * sides > MAX_WORK {
+                    return Err(DistributionError::TooComplex(*self));
+                }
+                Ok(sum_of(&Distribution::die(self.sides), self.count))
+            }
+            Some(keep) => {
+                if sides * count * count * self.kept_count() as f64 * sides > MAX_WORK {
+                    return Err(DistributionError::TooComplex(*self));
+                }
+                let (kept, highest) = match keep {
+                    Keep::Highest(n) => (n, true),
+                    Keep::Lowest(n) => (n, false),
+                    Keep::DropLowest(n) => (self.count - n, true),
+                    Keep::DropHighest(n) => (self.count - n, false),
+                };
+                Ok(keep_distribution(self.count, self.sides, kept, highest))
+            }
+        }
+    }
+}
+
+/// Distribution of `count` independent copies of `single` added together
+fn sum_of(single: &Distribution, count: i32) -> Distribution {
+    let mut total = Distribution::constant(0);
+    for _ in 0..count {
+        total = total.add(single);
+    }
+    total
+}
+
+/// A single exploding die: every maximum face adds another roll
+///
+/// `DiceTerm::roll` caps explosions per term rather than per die, so for a
+/// term of several dice the two only part ways once the whole term has
+/// exploded `MAX_EXPLOSIONS` times, which is far rarer than `EXPLOSION_EPSILON`.
+fn exploding_die(sides: i32) -> Distribution {
+    let p = 1.0 / sides as f64;
+    let mut pmf = BTreeMap::new();
+    let mut chain = 1.0;
+    let mut base = 0;
+
+    for explosions in 0..=MAX_EXPLOSIONS {
+        for face in 1..sides {
+            pmf.insert(base + face, chain * p);
+        }
+        chain *= p;
+        base += sides;
+        if chain < EXPLOSION_EPSILON && explosions < MAX_EXPLOSIONS {
+            // Fold the negligible remainder into the smallest outcome still possible
+            pmf.insert(base + 1, chain);
+            return Distribution { pmf };
+        }
+    }
+
+    // Every die up to the cap showed its maximum, and the roll stops there
+    pmf.insert(base, chain);
+    Distribution { pmf }
+}
+
+/// Distribution of keeping the `kept` highest (or lowest) of `count` dice
+///
+/// Faces are visited from best to worst; the state is the number of dice
+/// already assigned a face and the running sum of the kept ones. Because the
+/// best faces are assigned first, the first `kept` dice assigned are exactly
+/// the kept ones.
+fn keep_distribution(count: i32, sides: i32, kept: i32, highest: bool) -> Distribution {
+    let n = count as usize;
+    let p = 1.0 / sides as f64;
+
+    // states[assigned] = sum of kept dice -> probability weight
+    let mut states: Vec<BTreeMap<i32, f64>> = vec![BTreeMap::new(); n + 1];
+    states[0].insert(0, 1.0);
+
+    let faces: Vec<i32> = if highest {
+        (1..=sides).rev().collect()
+    } else {
+        (1..=sides).collect()
+    };
+
+    for face in faces {
+        let mut next: Vec<BTreeMap<i32, f64>> = vec![BTreeMap::new(); n + 1];
+        for (assigned, sums) in states.iter().enumerate() {
+            if sums.is_empty() {
+                continue;
+            }
+            let remaining = n - assigned;
+            // C(remaining, j) * p^j, built incrementally
+            let mut weight = 1.0;
+            for j in 0..=remaining {
+                if j > 0 {
+                    weight *= (remaining - j + 1) as f64 / j as f64 * p;
+                }
+                let newly_kept = (assigned + j).min(kept as usize) - assigned.min(kept as usize);
+                let added = face * newly_kept as i32;
+                for (sum, prob) in sums {
+                    *next[assigned + j].entry(sum + added).or_insert(0.0) += prob * weight;
+                }
+            }
+        }
+        states = next;
+    }
+
+    Distribution {
+        pmf: states.pop().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dice::SeededRoller;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_sum_distribution() {
+        let dist = Expr::parse("2d6+3").unwrap().distribution().unwrap();
+        assert_eq!(dist.min(), 5);
+        assert_eq!(dist.max(), 15);
+        assert!(close(dist.probability(10), 6.0 / 36.0));
+        assert!(close(dist.mean(), 10.0));
+        assert!(close(dist.variance(), 35.0 / 6.0));
+        assert!(close(dist.at_least(14), 3.0 / 36.0));
+    }
+
+    #[test]
+    fn test_keep_distribution() {
+        // Advantage on a d20: P(>= 11) = 1 - (10/20)^2
+        let advantage = Expr::parse("2d20kh1").unwrap().distribution().unwrap();
+        assert!(close(advantage.at_least(11), 0.75));
+        let disadvantage = Expr::parse("2d20kl1").unwrap().distribution().unwrap();
+        assert!(close(disadvantage.at_least(11), 0.25));
+
+        let stats = Expr::parse("4d6dl1").unwrap().distribution().unwrap();
+        assert_eq!(stats.min(), 3);
+        assert_eq!(stats.max(), 18);
+        assert!(close(stats.probability(18), 21.0 / 1296.0));
+        assert!(close(stats.mean(), 15869.0 / 1296.0));
+        assert!(close(stats.iter().map(|(_, p)| p).sum::<f64>(), 1.0));
+    }
+
+    #[test]
+    fn test_exploding_distribution() {
+        let dist = Expr::parse("1d6!").unwrap().distribution().unwrap();
+        assert!(close(dist.probability(6), 0.0));
+        assert!(close(dist.probability(7), 1.0 / 36.0));
+        assert!(close(dist.mean(), 4.2));
+        // Only totals the roller can produce: never a bare multiple of the maximum face
+        assert!(dist.iter().all(|(value, p)| value % 6 != 0 || p == 0.0));
+        assert!(close(dist.iter().map(|(_, p)| p).sum::<f64>(), 1.0));
+    }
+
+    #[test]
+    fn test_exploding_distribution_matches_roller() {
+        let term = DiceTerm { explode: true, ..DiceTerm::new(2, 4) };
+        let dist = term.distribution().unwrap();
+
+        let rolls = 40_000;
+        let mut counts: BTreeMap<i32, u32> = BTreeMap::new();
+        let mut roller = SeededRoller::new(7);
+        for _ in 0..rolls {
+            *counts.entry(term.roll(&mut roller).value).or_default() += 1;
+        }
+
+        assert!(counts.keys().all(|value| dist.probability(*value) > 0.0));
+        for (value, p) in dist.iter() {
+            let observed = counts.get(&value).copied().unwrap_or(0) as f64 / rolls as f64;
+            assert!((observed - p).abs() < 0.01, "{}: observed {} but expected {}", value, observed, p);
+        }
+    }
+}
@@ -0,0 +1,645 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use super::DiceRoller;
+
+/// Upper bound on the number of dice in a single term (e.g. "1000d6")
+pub const MAX_DICE: i32 = 1000;
+/// Upper bound on the number of faces of a single die
+pub const MAX_SIDES: i32 = 10_000;
+/// Maximum number of extra dice an exploding term may add
+pub const MAX_EXPLOSIONS: usize = 100;
+
+/// A parsed dice expression such as "4d6kh3 + 1d8 - 2"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Expr {
+    Constant(i32),
+    Dice(DiceTerm),
+    Negate(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Subtract(Box<Expr>, Box<Expr>),
+}
+
+/// A single group of dice, e.g. "4d6kh3" or "3d6!"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceTerm {
+    pub count: i32,
+    pub sides: i32,
+    pub keep: Option<Keep>,
+    pub explode: bool,
+}
+
+/// Which dice of a group count towards the total
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Highest(i32),
+    Lowest(i32),
+    DropHighest(i32),
+    DropLowest(i32),
+}
+
+/// A single die face produced while evaluating a term
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DieRoll {
+    pub face: i32,
+    pub sides: i32,
+    pub kept: bool,
+    pub exploded: bool,
+}
+
+/// The dice rolled for one term of an expression
+#[derive(Debug, Clone)]
+pub struct TermRoll {
+    pub term: DiceTerm,
+    pub dice: Vec<DieRoll>,
+    pub value: i32,
+}
+
+/// The outcome of evaluating an expression
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    pub total: i32,
+    pub terms: Vec<TermRoll>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    Empty,
+    UnexpectedChar(char),
+    UnexpectedEnd,
+    NumberTooLarge,
+    ZeroDice,
+    ZeroSides,
+    TooManyDice(i32),
+    TooManySides(i32),
+    KeepOutOfRange { keep: i32, count: i32 },
+    ExplodingSingleSide,
+    KeepWithExplode,
+    /// The expression could total more than an `i32` holds
+    ResultOutOfRange,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::Empty => write!(f, "empty dice expression"),
+            ParseErrorKind::UnexpectedChar(c) => {
+                write!(f, "unexpected character '{}' at position {}", c, self.position)
+            }
+            ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ParseErrorKind::NumberTooLarge => {
+                write!(f, "number too large at position {}", self.position)
+            }
+            ParseErrorKind::ZeroDice => write!(f, "dice count must be at least 1 at position {}", self.position),
+            ParseErrorKind::ZeroSides => write!(f, "dice must have at least 1 side at position {}", self.position),
+            ParseErrorKind::TooManyDice(n) => write!(f, "{} dice exceeds the limit of {}", n, MAX_DICE),
+            ParseErrorKind::TooManySides(n) => write!(f, "{} sides exceeds the limit of {}", n, MAX_SIDES),
+            ParseErrorKind::KeepOutOfRange { keep, count } => {
+                write!(f, "cannot keep or drop {} of {} dice", keep, count)
+            }
+            ParseErrorKind::ExplodingSingleSide => write!(f, "a one-sided die cannot explode"),
+            ParseErrorKind::KeepWithExplode => {
+                write!(f, "exploding dice cannot be combined with keep/drop")
+            }
+            ParseErrorKind::ResultOutOfRange => {
+                write!(f, "expression could overflow its total at position {}", self.position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self, ParseError> {
+        Parser::new(input).parse()
+    }
+
+    pub fn evaluate(&self, roller: &mut dyn DiceRoller) -> Evaluation {
+        let mut terms = Vec::new();
+        let total = self.eval_into(roller, &mut terms);
+        Evaluation { total, terms }
+    }
+
+    fn eval_into(&self, roller: &mut dyn DiceRoller, terms: &mut Vec<TermRoll>) -> i32 {
+        match self {
+            Expr::Constant(value) => *value,
+            Expr::Dice(term) => {
+                let roll = term.roll(roller);
+                let value = roll.value;
+                terms.push(roll);
+                value
+            }
+            Expr::Negate(inner) => -inner.eval_into(roller, terms),
+            Expr::Add(lhs, rhs) => lhs.eval_into(roller, terms) + rhs.eval_into(roller, terms),
+            Expr::Subtract(lhs, rhs) => lhs.eval_into(roller, terms) - rhs.eval_into(roller, terms),
+        }
+    }
+
+    /// Smallest value the expression can produce
+    pub fn min(&self) -> i32 {
+        match self {
+            Expr::Constant(value) => *value,
+            Expr::Dice(term) => term.min(),
+            Expr::Negate(inner) => -inner.max(),
+            Expr::Add(lhs, rhs) => lhs.min() + rhs.min(),
+            Expr::Subtract(lhs, rhs) => lhs.min() - rhs.max(),
+        }
+    }
+
+    /// Largest value the expression can produce (exploding dice count their explosion cap)
+    pub fn max(&self) -> i32 {
+        match self {
+            Expr::Constant(value) => *value,
+            Expr::Dice(term) => term.max(),
+            Expr::Negate(inner) => -inner.min(),
+            Expr::Add(lhs, rhs) => lhs.max() + rhs.max(),
+            Expr::Subtract(lhs, rhs) => lhs.max() - rhs.min(),
+        }
+    }
+
+    /// Smallest and largest values, widened so they cannot overflow
+    fn bounds(&self) -> (i64, i64) {
+        match self {
+            Expr::Constant(value) => (*value as i64, *value as i64),
+            Expr::Dice(term) => (term.min() as i64, term.max() as i64),
+            Expr::Negate(inner) => {
+                let (min, max) = inner.bounds();
+                (-max, -min)
+            }
+            Expr::Add(lhs, rhs) => {
+                let ((lmin, lmax), (rmin, rmax)) = (lhs.bounds(), rhs.bounds());
+                (lmin + rmin, lmax + rmax)
+            }
+            Expr::Subtract(lhs, rhs) => {
+                let ((lmin, lmax), (rmin, rmax)) = (lhs.bounds(), rhs.bounds());
+                (lmin - rmax, lmax - rmin)
+            }
+        }
+    }
+
+    /// All dice terms in the expression, left to right
+    pub fn dice_terms(&self) -> Vec<&DiceTerm> {
+        let mut terms = Vec::new();
+        self.collect_terms(&mut terms);
+        terms
+    }
+
+    fn collect_terms<'a>(&'a self, terms: &mut Vec<&'a DiceTerm>) {
+        match self {
+            Expr::Constant(_) => {}
+            Expr::Dice(term) => terms.push(term),
+            Expr::Negate(inner) => inner.collect_terms(terms),
+            Expr::Add(lhs, rhs) | Expr::Subtract(lhs, rhs) => {
+                lhs.collect_terms(terms);
+                rhs.collect_terms(terms);
+            }
+        }
+    }
+
+    /// True if the expression contains no dice
+    pub fn is_constant(&self) -> bool {
+        self.dice_terms().is_empty()
+    }
+}
+
+impl DiceTerm {
+    pub fn new(count: i32, sides: i32) -> Self {
+        Self {
+            count,
+            sides,
+            keep: None,
+            explode: false,
+        }
+    }
+
+    /// Number of dice that count towards the total
+    pub fn kept_count(&self) -> i32 {
+        match self.keep {
+            None => self.count,
+            Some(Keep::Highest(n)) | Some(Keep::Lowest(n)) => n,
+            Some(Keep::DropHighest(n)) | Some(Keep::DropLowest(n)) => self.count - n,
+        }
+    }
+
+    pub fn min(&self) -> i32 {
+        self.kept_count()
+    }
+
+    pub fn max(&self) -> i32 {
+        if self.explode {
+            (self.count + MAX_EXPLOSIONS as i32) * self.sides
+        } else {
+            self.kept_count() * self.sides
+        }
+    }
+
+    pub fn roll(&self, roller: &mut dyn DiceRoller) -> TermRoll {
+        let mut dice: Vec<DieRoll> = (0..self.count)
+            .map(|_| DieRoll {
+                face: roller.roll_die(self.sides),
+                sides: self.sides,
+                kept: true,
+                exploded: false,
+            })
+            .collect();
+
+        if self.explode {
+            // Every maximum face adds another die, which may explode in turn
+            let mut pending = dice.iter().filter(|d| d.face == self.sides).count();
+            let mut added = 0;
+            while pending > 0 && added < MAX_EXPLOSIONS {
+                pending -= 1;
+                added += 1;
+                let face = roller.roll_die(self.sides);
+                if face == self.sides {
+                    pending += 1;
+                }
+                dice.push(DieRoll {
+                    face,
+                    sides: self.sides,
+                    kept: true,
+                    exploded: true,
+                });
+            }
+        }
+
+        if let Some(keep) = self.keep {
+            apply_keep(&mut dice, keep);
+        }
+
+        let value = dice.iter().filter(|d| d.kept).map(|d| d.face).sum();
+        TermRoll {
+            term: *self,
+            dice,
+            value,
+        }
+    }
+}
+
+/// Mark dice as dropped according to a keep rule, preserving roll order
+fn apply_keep(dice: &mut [DieRoll], keep: Keep) {
+    let mut order: Vec<usize> = (0..dice.len()).collect();
+    // Stable sort so ties drop the earliest rolled die first
+    order.sort_by_key(|&i| dice[i].face);
+
+    let count = dice.len();
+    let dropped: Vec<usize> = match keep {
+        Keep::Highest(n) => order[..count - n as usize].to_vec(),
+        Keep::DropLowest(n) => order[..n as usize].to_vec(),
+        Keep::Lowest(n) => order[n as usize..].to_vec(),
+        Keep::DropHighest(n) => order[count - n as usize..].to_vec(),
+    };
+
+    for i in dropped {
+        dice[i].kept = false;
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Constant(value) => write!(f, "{}", value),
+            Expr::Dice(term) => write!(f, "{}", term),
+            Expr::Negate(inner) => write!(f, "-{}", inner),
+            Expr::Add(lhs, rhs) => write!(f, "{} + {}", lhs, rhs),
+            Expr::Subtract(lhs, rhs) => write!(f, "{} - {}", lhs, rhs),
+        }
+    }
+}
+
+impl fmt::Display for DiceTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.keep {
+            Some(Keep::Highest(n)) => write!(f, "kh{}", n)?,
+            Some(Keep::Lowest(n)) => write!(f, "kl{}", n)?,
+            Some(Keep::DropHighest(n)) => write!(f, "dh{}", n)?,
+            Some(Keep::DropLowest(n)) => write!(f, "dl{}", n)?,
+            None => {}
+        }
+        if self.explode {
+            write!(f, "!")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expr::parse(s)
+    }
+}
+
+impl TryFrom<String> for Expr {
+    type Error = ParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Expr::parse(&value)
+    }
+}
+
+impl From<Expr> for String {
+    fn from(expr: Expr) -> Self {
+        expr.to_string()
+    }
+}
+
+/// Recursive descent parser for dice notation
+///
+/// Grammar (whitespace may separate tokens but never split a number or term):
+///   expr  := sign? term (('+' | '-') term)*
+///   term  := number? 'd' (number | '%') keep? '!'? | number
+///   keep  := ('kh' | 'kl' | 'dh' | 'dl' | 'k') number
+struct Parser {
+    chars: Vec<(usize, char)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Self {
+        Self {
+            chars: input.char_indices().collect(),
+            pos: 0,
+            len: input.len(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return Err(self.error(ParseErrorKind::Empty));
+        }
+
+        let negate = match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                true
+            }
+            Some('+') => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        };
+
+        self.skip_whitespace();
+        let mut expr = self.parse_term()?;
+        if negate {
+            expr = self.in_range(Expr::Negate(Box::new(expr)))?;
+        }
+
+        self.skip_whitespace();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            self.skip_whitespace();
+            let rhs = match c {
+                '+' | '-' => self.parse_term()?,
+                other => {
+                    self.pos -= 1;
+                    return Err(self.error(ParseErrorKind::UnexpectedChar(other)));
+                }
+            };
+            expr = self.in_range(if c == '+' {
+                Expr::Add(Box::new(expr), Box::new(rhs))
+            } else {
+                Expr::Subtract(Box::new(expr), Box::new(rhs))
+            })?;
+            self.skip_whitespace();
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, ParseError> {
+        let start = self.offset();
+        let count = self.parse_number()?;
+
+        if !matches!(self.peek(), Some('d') | Some('D')) {
+            return match count {
+                Some(value) => Ok(Expr::Constant(value)),
+                None => Err(self.unexpected()),
+            };
+        }
+        self.pos += 1;
+
+        let count = count.unwrap_or(1);
+        if count < 1 {
+            return Err(ParseError { position: start, kind: ParseErrorKind::ZeroDice });
+        }
+        if count > MAX_DICE {
+            return Err(ParseError { position: start, kind: ParseErrorKind::TooManyDice(count) });
+        }
+
+        let sides_pos = self.offset();
+        let sides = if self.peek() == Some('%') {
+            self.pos += 1;
+            100
+        } else {
+            self.parse_number()?.ok_or_else(|| self.unexpected())?
+        };
+        if sides < 1 {
+            return Err(ParseError { position: sides_pos, kind: ParseErrorKind::ZeroSides });
+        }
+        if sides > MAX_SIDES {
+            return Err(ParseError { position: sides_pos, kind: ParseErrorKind::TooManySides(sides) });
+        }
+
+        let mut term = DiceTerm::new(count, sides);
+
+        let keep_pos = self.offset();
+        if let Some(keep) = self.parse_keep()? {
+            let n = match keep {
+                Keep::Highest(n) | Keep::Lowest(n) | Keep::DropHighest(n) | Keep::DropLowest(n) => n,
+            };
+            if n > count {
+                return Err(ParseError {
+                    position: keep_pos,
+                    kind: ParseErrorKind::KeepOutOfRange { keep: n, count },
+                });
+            }
+            term.keep = Some(keep);
+        }
+
+        if self.peek() == Some('!') {
+            let explode_pos = self.offset();
+            self.pos += 1;
+            if sides == 1 {
+                return Err(ParseError { position: explode_pos, kind: ParseErrorKind::ExplodingSingleSide });
+            }
+            if term.keep.is_some() {
+                return Err(ParseError { position: explode_pos, kind: ParseErrorKind::KeepWithExplode });
+            }
+            term.explode = true;
+        }
+
+        Ok(Expr::Dice(term))
+    }
+
+    /// Reject `expr` if its total could overflow; checking each step as it
+    /// is built keeps every partial sum in range during evaluation too
+    fn in_range(&self, expr: Expr) -> Result<Expr, ParseError> {
+        let (min, max) = expr.bounds();
+        if min < i32::MIN as i64 || max > i32::MAX as i64 {
+            return Err(self.error(ParseErrorKind::ResultOutOfRange));
+        }
+        Ok(expr)
+    }
+
+    fn parse_keep(&mut self) -> Result<Option<Keep>, ParseError> {
+        let make: fn(i32) -> Keep = match (self.peek(), self.peek_at(1)) {
+            (Some('k'), Some('h')) => {
+                self.pos += 2;
+                Keep::Highest
+            }
+            (Some('k'), Some('l')) => {
+                self.pos += 2;
+                Keep::Lowest
+            }
+            (Some('d'), Some('h')) => {
+                self.pos += 2;
+                Keep::DropHighest
+            }
+            (Some('d'), Some('l')) => {
+                self.pos += 2;
+                Keep::DropLowest
+            }
+            (Some('k'), _) => {
+                self.pos += 1;
+                Keep::Highest
+            }
+            _ => return Ok(None),
+        };
+
+        let n = self.parse_number()?.ok_or_else(|| self.unexpected())?;
+        Ok(Some(make(n)))
+    }
+
+    fn parse_number(&mut self) -> Result<Option<i32>, ParseError> {
+        let start = self.offset();
+        let mut value: Option<i32> = None;
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(10)) {
+            value = Some(
+                value
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(digit as i32))
+                    .ok_or(ParseError { position: start, kind: ParseErrorKind::NumberTooLarge })?,
+            );
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    /// Step over whitespace; only called between tokens, so a gap inside a
+    /// term is left in place and rejected as an unexpected character
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<char> {
+        self.chars.get(self.pos + ahead).map(|(_, c)| *c)
+    }
+
+    fn offset(&self) -> usize {
+        self.chars.get(self.pos).map(|(i, _)| *i).unwrap_or(self.len)
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(c) => self.error(ParseErrorKind::UnexpectedChar(c)),
+            None => self.error(ParseErrorKind::UnexpectedEnd),
+        }
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError { position: self.offset(), kind }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dice::{ScriptedRoller, SeededRoller};
+
+    #[test]
+    fn test_parse_and_display() {
+        let expr = Expr::parse("4d6kh3 + 1d8 - 2").unwrap();
+        assert_eq!(expr.to_string(), "4d6kh3 + 1d8 - 2");
+        assert_eq!(expr.dice_terms().len(), 2);
+        assert_eq!(expr.min(), 3 + 1 - 2);
+        assert_eq!(expr.max(), 18 + 8 - 2);
+
+        assert_eq!(Expr::parse("d%").unwrap(), Expr::Dice(DiceTerm::new(1, 100)));
+        assert!(Expr::parse("3d6!").unwrap().dice_terms()[0].explode);
+        assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expr::parse("").unwrap_err().kind, ParseErrorKind::Empty);
+        assert_eq!(Expr::parse("2d").unwrap_err().kind, ParseErrorKind::UnexpectedEnd);
+        assert_eq!(Expr::parse("2d6 * 2").unwrap_err().kind, ParseErrorKind::UnexpectedChar('*'));
+        assert_eq!(
+            Expr::parse("2d20kl3").unwrap_err().kind,
+            ParseErrorKind::KeepOutOfRange { keep: 3, count: 2 }
+        );
+        assert_eq!(Expr::parse("1d6 + 0d4").unwrap_err().position, 6);
+
+        // Whitespace separates tokens but cannot split one
+        assert_eq!(
+            Expr::parse("1 0d6").unwrap_err(),
+            ParseError { position: 2, kind: ParseErrorKind::UnexpectedChar('0') }
+        );
+        assert_eq!(Expr::parse("2d 6").unwrap_err().kind, ParseErrorKind::UnexpectedChar(' '));
+        assert_eq!(Expr::parse("4d6 kh3").unwrap_err().kind, ParseErrorKind::UnexpectedChar('k'));
+        assert_eq!(Expr::parse("  - 1d6+2 ").unwrap().to_string(), Expr::parse("-1d6 + 2").unwrap().to_string());
+        assert_eq!(Expr::parse(" \t").unwrap_err().kind, ParseErrorKind::Empty);
+
+        // Totals that could overflow are refused rather than wrapping when rolled
+        assert_eq!(Expr::parse("2147483647 + 1").unwrap_err().kind, ParseErrorKind::ResultOutOfRange);
+        assert_eq!(Expr::parse("2147483000 + 1000d6").unwrap_err().kind, ParseErrorKind::ResultOutOfRange);
+        assert_eq!(Expr::parse("-2147483647 - 2").unwrap_err().kind, ParseErrorKind::ResultOutOfRange);
+        assert_eq!(Expr::parse("2147483647 - 1d6").unwrap().max(), 2147483646);
+    }
+
+    #[test]
+    fn test_evaluate_keep() {
+        let mut roller = SeededRoller::new(7);
+        let expr = Expr::parse("4d6dl1 + 1").unwrap();
+        for _ in 0..100 {
+            let result = expr.evaluate(&mut roller);
+            let term = &result.terms[0];
+            assert_eq!(term.dice.iter().filter(|d| !d.kept).count(), 1);
+            let dropped = term.dice.iter().find(|d| !d.kept).unwrap().face;
+            assert!(term.dice.iter().all(|d| d.face >= dropped));
+            assert_eq!(result.total, term.value + 1);
+            assert!(result.total >= 4 && result.total <= 19);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_exploding() {
+        let expr = Expr::parse("2d6! - 1").unwrap();
+        let mut roller = ScriptedRoller::new([6, 2, 6, 3]);
+        let result = expr.evaluate(&mut roller);
+        assert_eq!(result.total, 6 + 2 + 6 + 3 - 1);
+        assert_eq!(result.terms[0].dice.iter().filter(|d| d.exploded).count(), 2);
+    }
+}
@@ -0,0 +1,156 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Source of die faces for every dice consumer
+pub trait DiceRoller {
+    /// Roll a single die, returning a face in `1..=sides`
+    fn roll_die(&mut self, sides: i32) -> i32;
+
+    /// Where the roller is up to, for rollers that can be saved and resumed
+    fn state(&self) -> Option<RollerState> {
+        None
+    }
+}
+
+/// A saved roller, restored to produce exactly the faces the original would have
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RollerState {
+    Seeded { seed: u64, word_pos: u128 },
+    Scripted { faces: Vec<i32> },
+    Thread,
+}
+
+impl RollerState {
+    pub fn restore(&self) -> Box<dyn DiceRoller> {
+        match self {
+            RollerState::Seeded { seed, word_pos } => {
+                let mut roller = SeededRoller::new(*seed);
+                roller.rng.set_word_pos(*word_pos);
+                Box::new(roller)
+            }
+            RollerState::Scripted { faces } => Box::new(ScriptedRoller::new(faces.iter().copied())),
+            RollerState::Thread => Box::new(ThreadRoller),
+        }
+    }
+}
+
+/// Deterministic roller backed by a seeded ChaCha12 generator (what `StdRng` uses)
+#[derive(Debug, Clone)]
+pub struct SeededRoller {
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl SeededRoller {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl DiceRoller for SeededRoller {
+    fn roll_die(&mut self, sides: i32) -> i32 {
+        self.rng.gen_range(1..=sides)
+    }
+
+    fn state(&self) -> Option<RollerState> {
+        Some(RollerState::Seeded {
+            seed: self.seed,
+            word_pos: self.rng.get_word_pos(),
+        })
+    }
+}
+
+/// Roller that replays a fixed sequence of faces, for tests and scripted scenes
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedRoller {
+    faces: VecDeque<i32>,
+}
+
+impl ScriptedRoller {
+    pub fn new(faces: impl IntoIterator<Item = i32>) -> Self {
+        Self {
+            faces: faces.into_iter().collect(),
+        }
+    }
+
+    pub fn push(&mut self, face: i32) {
+        self.faces.push_back(face);
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.faces.len()
+    }
+}
+
+impl DiceRoller for ScriptedRoller {
+    fn roll_die(&mut self, sides: i32) -> i32 {
+        let face = self
+            .faces
+            .pop_front()
+            .expect("ScriptedRoller ran out of scripted faces");
+        assert!(
+            face >= 1 && face <= sides,
+            "scripted face {} is not valid for a d{}",
+            face,
+            sides
+        );
+        face
+    }
+
+    fn state(&self) -> Option<RollerState> {
+        Some(RollerState::Scripted {
+            faces: self.faces.iter().copied().collect(),
+        })
+    }
+}
+
+/// Non-deterministic roller using the thread-local RNG
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadRoller;
+
+impl DiceRoller for ThreadRoller {
+    fn roll_die(&mut self, sides: i32) -> i32 {
+        rand::thread_rng().gen_range(1..=sides)
+    }
+
+    fn state(&self) -> Option<RollerState> {
+        Some(RollerState::Thread)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_roller_is_reproducible() {
+        let mut a = SeededRoller::new(42);
+        let mut b = SeededRoller::new(42);
+        let first: Vec<i32> = (0..20).map(|_| a.roll_die(20)).collect();
+        let second: Vec<i32> = (0..20).map(|_| b.roll_die(20)).collect();
+        assert_eq!(first, second);
+        assert!(first.iter().all(|face| (1..=20).contains(face)));
+
+        // A restored roller carries on from the same point
+        let mut restored = a.state().unwrap().restore();
+        let next: Vec<i32> = (0..20).map(|_| a.roll_die(6)).collect();
+        assert_eq!(next, (0..20).map(|_| restored.roll_die(6)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_scripted_roller() {
+        let mut roller = ScriptedRoller::new([3, 20]);
+        assert_eq!(roller.roll_die(6), 3);
+        assert_eq!(roller.roll_die(20), 20);
+        assert_eq!(roller.remaining(), 0);
+    }
+}
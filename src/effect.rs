@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use crate::{
+    character::CharacterStats,
+    dice::{DiceRoller, Expr, RollMode},
+    item::{DamageDefenses, DamageType},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EffectKind {
+    Poisoned,
+    Stunned,
+    Prone,
+    Burning,
+    Blessed,
+    Restrained,
+    Hasted,
+    Slowed,
+    Custom(String),
+}
+
+/// How long an effect lasts, counted down at the end of the affected creature's turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectDuration {
+    Rounds(u32),
+    UntilRemoved,
+}
+
+/// What happens when an effect of the same kind is applied again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StackingRule {
+    /// Keep one instance and take the longer duration
+    Refresh,
+    /// Add stacks (each multiplying the effect's modifiers) up to a cap
+    Stack { max_stacks: u32 },
+    /// Ignore the new application while one is active
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementModifier {
+    Flat(i32),
+    Halved,
+    Doubled,
+    Immobilized,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerTiming {
+    TurnStart,
+    TurnEnd,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerAction {
+    Damage { dice: Expr, damage_type: DamageType },
+    Heal { dice: Expr },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectTrigger {
+    pub timing: TriggerTiming,
+    pub action: TriggerAction,
+}
+
+/// Ability score changes applied per stack of an effect
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatModifiers {
+    pub strength: i32,
+    pub dexterity: i32,
+    pub constitution: i32,
+    pub intelligence: i32,
+    pub wisdom: i32,
+    pub charisma: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: EffectKind,
+    pub duration: EffectDuration,
+    pub stacking: StackingRule,
+    #[serde(default = "default_stacks")]
+    pub stacks: u32,
+    #[serde(default)]
+    pub stat_modifiers: StatModifiers,
+    #[serde(default)]
+    pub movement: Option<MovementModifier>,
+    #[serde(default)]
+    pub defenses: DamageDefenses,
+    /// Advantage or disadvantage on this creature's own attack rolls
+    #[serde(default)]
+    pub attack_mode: RollMode,
+    /// Advantage or disadvantage granted to attacks made against this creature
+    #[serde(default)]
+    pub incoming_attack_mode: RollMode,
+    /// Extra dice added to this creature's attack rolls
+    #[serde(default)]
+    pub attack_bonus: Option<Expr>,
+    /// The creature loses its actions while affected
+    #[serde(default)]
+    pub incapacitated: bool,
+    #[serde(default)]
+    pub triggers: Vec<EffectTrigger>,
+    /// Who applied the effect; the first of them if several applications merged
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Everyone else whose application merged into this one
+    #[serde(default)]
+    pub merged_sources: Vec<String>,
+}
+
+fn default_stacks() -> u32 {
+    1
+}
+
+/// Result of an effect trigger or expiry, reported back to the combat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectTick {
+    Damaged { kind: EffectKind, amount: i32, damage_type: DamageType },
+    Healed { kind: EffectKind, amount: i32 },
+    Expired { kind: EffectKind },
+}
+
+impl StatusEffect {
+    pub fn new(kind: EffectKind, duration: EffectDuration) -> Self {
+        Self {
+            kind,
+            duration,
+            stacking: StackingRule::Refresh,
+            stacks: 1,
+            stat_modifiers: StatModifiers::default(),
+            movement: None,
+            defenses: DamageDefenses::default(),
+            attack_mode: RollMode::Normal,
+            incoming_attack_mode: RollMode::Normal,
+            attack_bonus: None,
+            incapacitated: false,
+            triggers: Vec::new(),
+            source: None,
+            merged_sources: Vec::new(),
+        }
+    }
+
+    /// Disadvantage on attack rolls
+    pub fn poisoned(rounds: u32) -> Self {
+        let mut effect = Self::new(EffectKind::Poisoned, EffectDuration::Rounds(rounds));
+        effect.attack_mode = RollMode::Disadvantage;
+        effect
+    }
+
+    /// Cannot act or move, and attackers have advantage
+    pub fn stunned(rounds: u32) -> Self {
+        let mut effect = Self::new(EffectKind::Stunned, EffectDuration::Rounds(rounds));
+        effect.incapacitated = true;
+        effect.movement = Some(MovementModifier::Immobilized);
+        effect.incoming_attack_mode = RollMode::Advantage;
+        effect
+    }
+
+    /// Lying down: half speed, own attacks at disadvantage, attackers have advantage
+    pub fn prone() -> Self {
+        let mut effect = Self::new(EffectKind::Prone, EffectDuration::UntilRemoved);
+        effect.movement = Some(MovementModifier::Halved);
+        effect.attack_mode = RollMode::Disadvantage;
+        effect.incoming_attack_mode = RollMode::Advantage;
+        effect
+    }
+
+    /// 1d4 fire damage at the start of each turn per stack
+    pub fn burning(rounds: u32) -> Self {
+        let mut effect = Self::new(EffectKind::Burning, EffectDuration::Rounds(rounds));
+        effect.stacking = StackingRule::Stack { max_stacks: 3 };
+        effect.triggers.push(EffectTrigger {
+            timing: TriggerTiming::TurnStart,
+            action: TriggerAction::Damage {
+                dice: Expr::parse("1d4").expect("valid dice notation"),
+                damage_type: DamageType::Fire,
+            },
+        });
+        effect
+    }
+
+    /// Adds 1d4 to attack rolls
+    pub fn blessed(rounds: u32) -> Self {
+        let mut effect = Self::new(EffectKind::Blessed, EffectDuration::Rounds(rounds));
+        effect.attack_bonus = Some(Expr::parse("1d4").expect("valid dice notation"));
+        effect
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.duration == EffectDuration::Rounds(0)
+    }
+
+    /// Merge a new application of the same kind according to the stacking rule
+    pub fn stack_with(&mut self, other: StatusEffect) {
+        for source in other.source.iter().chain(&other.merged_sources) {
+            if self.source.as_ref() != Some(source) && !self.merged_sources.contains(source) {
+                self.merged_sources.push(source.clone());
+            }
+        }
+        match self.stacking {
+            StackingRule::Ignore => {}
+            StackingRule::Refresh => {
+                self.duration = longer(self.duration, other.duration);
+            }
+            StackingRule::Stack { max_stacks } => {
+                self.stacks = (self.stacks + other.stacks).min(max_stacks);
+                self.duration = longer(self.duration, other.duration);
+            }
+        }
+    }
+
+    /// Take `source`'s application away, handing the effect to another source if
+    /// one merged in; true if nobody is left holding it
+    pub fn drop_source(&mut self, source: &str) -> bool {
+        if self.source.as_deref() == Some(source) {
+            if self.merged_sources.is_empty() {
+                return true;
+            }
+            self.source = Some(self.merged_sources.remove(0));
+        } else {
+            self.merged_sources.retain(|s| s != source);
+        }
+        false
+    }
+
+    /// Add this effect's modifiers (scaled by stacks) onto a stat block
+    pub fn apply_to_stats(&self, stats: &mut CharacterStats) {
+        let n = self.stacks as i32;
+        stats.strength += self.stat_modifiers.strength * n;
+        stats.dexterity += self.stat_modifiers.dexterity * n;
+        stats.constitution += self.stat_modifiers.constitution * n;
+        stats.intelligence += self.stat_modifiers.intelligence * n;
+        stats.wisdom += self.stat_modifiers.wisdom * n;
+        stats.charisma += self.stat_modifiers.charisma * n;
+    }
+
+    /// Run this effect's triggers for a timing, returning what they did
+    pub fn fire_triggers(&self, timing: TriggerTiming, roller: &mut dyn DiceRoller) -> Vec<EffectTick> {
+        let mut ticks = Vec::new();
+        for trigger in self.triggers.iter().filter(|t| t.timing == timing) {
+            match &trigger.action {
+                TriggerAction::Damage { dice, damage_type } => {
+                    let amount = (0..self.stacks).map(|_| dice.evaluate(roller).total.max(0)).sum();
+                    ticks.push(EffectTick::Damaged {
+                        kind: self.kind.clone(),
+                        amount,
+                        damage_type: *damage_type,
+                    });
+                }
+                TriggerAction::Heal { dice } => {
+                    let amount = (0..self.stacks).map(|_| dice.evaluate(roller).total.max(0)).sum();
+                    ticks.push(EffectTick::Healed {
+                        kind: self.kind.clone(),
+                        amount,
+                    });
+                }
+            }
+        }
+        ticks
+    }
+}
+
+fn longer(a: EffectDuration, b: EffectDuration) -> EffectDuration {
+    match (a, b) {
+        (EffectDuration::Rounds(x), EffectDuration::Rounds(y)) => EffectDuration::Rounds(x.max(y)),
+        _ => EffectDuration::UntilRemoved,
+    }
+}
+
+impl MovementModifier {
+    pub fn apply(&self, speed: i32) -> i32 {
+        match self {
+            MovementModifier::Flat(amount) => (speed + amount).max(0),
+            MovementModifier::Halved => speed / 2,
+            MovementModifier::Doubled => speed * 2,
+            MovementModifier::Immobilized => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dice::ScriptedRoller;
+
+    #[test]
+    fn test_stacking_rules() {
+        let mut burning = StatusEffect::burning(2);
+        burning.stack_with(StatusEffect::burning(3));
+        burning.stack_with(StatusEffect::burning(1));
+        burning.stack_with(StatusEffect::burning(1));
+        assert_eq!(burning.stacks, 3);
+        assert_eq!(burning.duration, EffectDuration::Rounds(3));
+
+        let mut prone = StatusEffect::prone();
+        prone.stack_with(StatusEffect::prone());
+        assert_eq!(prone.stacks, 1);
+    }
+
+    #[test]
+    fn test_triggers_scale_with_stacks() {
+        let mut burning = StatusEffect::burning(2);
+        burning.stacks = 2;
+        let mut roller = ScriptedRoller::new([3, 4]);
+        let ticks = burning.fire_triggers(TriggerTiming::TurnStart, &mut roller);
+        assert_eq!(
+            ticks,
+            vec![EffectTick::Damaged { kind: EffectKind::Burning, amount: 7, damage_type: DamageType::Fire }]
+        );
+        assert!(burning.fire_triggers(TriggerTiming::TurnEnd, &mut roller).is_empty());
+    }
+}
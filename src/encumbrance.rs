@@ -0,0 +1,159 @@
+//! Carrying capacity and the penalties for carrying too much
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{Character, character::Ability, race::Size};
+
+/// Weight a medium creature can carry per point of strength
+pub const CAPACITY_PER_STRENGTH: f32 = 15.0;
+
+/// How weighed down a character is by everything they carry and wear
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Encumbrance {
+    Unencumbered,
+    /// Over a third of capacity: speed drops by 2 hexes
+    Encumbered,
+    /// Over two thirds of capacity: speed drops by 4 hexes, with disadvantage on
+    /// attacks and on strength, dexterity and constitution checks and saves
+    HeavilyEncumbered,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InventoryError {
+    /// Taking the item would bring the total `weight` over the carrying capacity
+    TooHeavy { weight: f32, capacity: f32 },
+    NotInInventory,
+    NotConsumable,
+    /// The named consumable would do nothing for whoever uses it
+    NoEffect(String),
+}
+
+impl Encumbrance {
+    /// Hexes taken off the character's speed
+    pub fn speed_penalty(self) -> i32 {
+        match self {
+            Encumbrance::Unencumbered => 0,
+            Encumbrance::Encumbered => 2,
+            Encumbrance::HeavilyEncumbered => 4,
+        }
+    }
+}
+
+impl Size {
+    /// Scales carrying capacity; small creatures carry as much as medium ones
+    pub fn capacity_multiplier(self) -> f32 {
+        match self {
+            Size::Tiny => 0.5,
+            Size::Small | Size::Medium => 1.0,
+            Size::Large => 2.0,
+            Size::Huge => 4.0,
+            Size::Gargantuan => 8.0,
+        }
+    }
+}
+
+impl Character {
+    /// Most the character can carry, worn equipment included
+    pub fn carrying_capacity(&self) -> f32 {
+        let strength = self.get_total_stats().score(Ability::Strength).max(0) as f32;
+        strength * CAPACITY_PER_STRENGTH * self.size().capacity_multiplier()
+    }
+
+    /// Weight of the inventory and of everything equipped
+    pub fn current_weight(&self) -> f32 {
+        self.inventory.iter().chain(self.equipment.values()).map(|item| item.weight).sum()
+    }
+
+    pub fn encumbrance(&self) -> Encumbrance {
+        let capacity = self.carrying_capacity();
+        // With no strength to speak of, any load at all is too much
+        if capacity <= 0.0 {
+            return Encumbrance::HeavilyEncumbered;
+        }
+        let load = self.current_weight() / capacity;
+        if load > 2.0 / 3.0 {
+            Encumbrance::HeavilyEncumbered
+        } else if load > 1.0 / 3.0 {
+            Encumbrance::Encumbered
+        } else {
+            Encumbrance::Unencumbered
+        }
+    }
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InventoryError::TooHeavy { weight, capacity } => {
+                write!(f, "carrying {} would exceed the capacity of {}", weight, capacity)
+            }
+            InventoryError::NotInInventory => write!(f, "item not found in inventory"),
+            InventoryError::NotConsumable => write!(f, "item is not a consumable"),
+            InventoryError::NoEffect(name) => write!(f, "{} has no effect when used", name),
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        character::CharacterStats,
+        dice::{RollMode, ScriptedRoller},
+        item::{ConsumableType, Item, RaceType},
+        race::RaceDefinition,
+    };
+
+    fn sack(weight: f32) -> Item {
+        let mut item = Item::new_consumable("Rations".to_string(), ConsumableType::Food, String::new());
+        item.weight = weight;
+        item
+    }
+
+    #[test]
+    fn test_encumbrance_tiers() {
+        let stats = CharacterStats::from_scores([10, 10, 10, 10, 10, 10]);
+        let mut porter = Character::new("Porter".to_string(), RaceType::Human, stats);
+        assert_eq!(porter.carrying_capacity(), 150.0);
+
+        porter.add_to_inventory(sack(60.0)).unwrap();
+        assert_eq!(porter.encumbrance(), Encumbrance::Encumbered);
+        assert_eq!(porter.effective_speed(), 4);
+
+        porter.add_to_inventory(sack(60.0)).unwrap();
+        assert_eq!(porter.encumbrance(), Encumbrance::HeavilyEncumbered);
+        assert_eq!(porter.effective_speed(), 2);
+        assert_eq!(porter.attack_mode(), RollMode::Disadvantage);
+        assert!(!porter.saving_throw(Ability::Constitution, 10, &mut ScriptedRoller::new([18, 3])).success);
+
+        assert_eq!(
+            porter.add_to_inventory(sack(40.0)),
+            Err(InventoryError::TooHeavy { weight: 160.0, capacity: 150.0 })
+        );
+    }
+
+    #[test]
+    fn test_size_scales_capacity() {
+        let stats = CharacterStats::from_scores([10, 10, 10, 10, 10, 10]);
+        let mut giant = Character::new("Giant".to_string(), RaceType::Human, stats);
+        giant.set_race(&RaceDefinition { name: "Ogre".to_string(), size: Size::Large, ..Default::default() });
+        assert_eq!(giant.carrying_capacity(), 300.0);
+
+        giant.add_to_inventory(sack(90.0)).unwrap();
+        assert_eq!(giant.encumbrance(), Encumbrance::Unencumbered);
+    }
+
+    #[test]
+    fn test_zero_strength_is_heavily_encumbered() {
+        let stats = CharacterStats::from_scores([0, 10, 10, 10, 10, 10]);
+        let husk = Character::new("Husk".to_string(), RaceType::Human, stats);
+        assert_eq!(husk.carrying_capacity(), 0.0);
+        assert_eq!(husk.encumbrance(), Encumbrance::HeavilyEncumbered);
+        assert_eq!(
+            husk.clone().add_to_inventory(sack(1.0)),
+            Err(InventoryError::TooHeavy { weight: 1.0, capacity: 0.0 })
+        );
+    }
+}
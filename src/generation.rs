@@ -0,0 +1,329 @@
+//! Character generation: ability scores, racial adjustments and a builder
+//! for ready-to-play characters
+
+use std::fmt;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Builder;
+use crate::{
+    Character,
+    character::{Ability, CharacterStats, ability_modifier},
+    class::{ClassError, ClassRegistry, experience_for_level, MAX_LEVEL},
+    dice::{DiceRoller, Expr, SeededRoller},
+    item::{Item, RaceType},
+    race::RaceDefinition,
+    skill::Training,
+};
+
+/// Scores handed out by the standard array, best first
+pub const STANDARD_ARRAY: [i32; 6] = [15, 14, 13, 12, 10, 8];
+/// Points available to spend with point buy
+pub const POINT_BUY_BUDGET: i32 = 27;
+/// Lowest and highest scores point buy can produce, before racial bonuses
+pub const POINT_BUY_RANGE: (i32, i32) = (8, 15);
+
+/// How the six ability scores are produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AbilityScores {
+    /// 4d6 dropping the lowest die, rolled in `Ability::ALL` order
+    Rolled,
+    /// The standard array, best scores going to the listed abilities first
+    /// and the rest following in `Ability::ALL` order
+    StandardArray(Vec<Ability>),
+    /// Scores bought from `POINT_BUY_BUDGET`
+    PointBuy(CharacterStats),
+    /// Scores used exactly as given
+    Fixed(CharacterStats),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerationError {
+    /// A point-buy score outside `POINT_BUY_RANGE`
+    ScoreOutOfRange { ability: Ability, score: i32 },
+    OverBudget { cost: i32 },
+    InvalidLevel(i32),
+    Class(ClassError),
+    Equipment { item: String, reason: String },
+}
+
+/// Roll one ability score: 4d6, dropping the lowest die
+pub fn roll_ability_score(roller: &mut dyn DiceRoller) -> i32 {
+    Expr::parse("4d6kh3").expect("valid dice notation").evaluate(roller).total
+}
+
+/// Roll all six scores in `Ability::ALL` order
+pub fn roll_ability_scores(roller: &mut dyn DiceRoller) -> CharacterStats {
+    CharacterStats::from_scores(std::array::from_fn(|_| roll_ability_score(roller)))
+}
+
+/// Assign the standard array, best scores to `priority` first
+pub fn standard_array(priority: &[Ability]) -> CharacterStats {
+    let mut order: Vec<Ability> = Vec::with_capacity(6);
+    for &ability in priority.iter().chain(Ability::ALL.iter()) {
+        if !order.contains(&ability) {
+            order.push(ability);
+        }
+    }
+    let mut stats = CharacterStats::from_scores([0; 6]);
+    for (ability, score) in order.into_iter().zip(STANDARD_ARRAY) {
+        *stats.score_mut(ability) = score;
+    }
+    stats
+}
+
+/// Points a single score costs: one per point up to 13, two per point after
+pub fn point_buy_cost(score: i32) -> Option<i32> {
+    let (min, max) = POINT_BUY_RANGE;
+    if !(min..=max).contains(&score) {
+        return None;
+    }
+    Some((score - min) + (score - 13).max(0))
+}
+
+/// Check a point-buy spread fits the budget, returning what it cost
+pub fn validate_point_buy(stats: &CharacterStats) -> Result<i32, GenerationError> {
+    let mut cost = 0;
+    for ability in Ability::ALL {
+        let score = stats.score(ability);
+        cost += point_buy_cost(score).ok_or(GenerationError::ScoreOutOfRange { ability, score })?;
+    }
+    if cost > POINT_BUY_BUDGET {
+        return Err(GenerationError::OverBudget { cost });
+    }
+    Ok(cost)
+}
+
+/// Add a race's ability bonuses to a set of scores
+pub fn apply_racial_bonuses(stats: &mut CharacterStats, race: &RaceDefinition) {
+    for (&ability, &bonus) in &race.ability_bonuses {
+        *stats.score_mut(ability) += bonus;
+    }
+}
+
+/// Builds a complete character, rolling every die from one seed so the same
+/// settings always produce the same character
+#[derive(Debug, Clone)]
+pub struct CharacterBuilder {
+    name: String,
+    race: RaceDefinition,
+    seed: u64,
+    scores: Option<AbilityScores>,
+    class: Option<String>,
+    level: i32,
+    items: Vec<Item>,
+}
+
+impl CharacterBuilder {
+    pub fn new(name: impl Into<String>, race: RaceType, seed: u64) -> Self {
+        Self {
+            name: name.into(),
+            race: RaceDefinition::builtin(&race).clone(),
+            seed,
+            scores: None,
+            class: None,
+            level: 1,
+            items: Vec::new(),
+        }
+    }
+
+    /// Use a race loaded from data in place of the built-in one
+    pub fn race(mut self, race: RaceDefinition) -> Self {
+        self.race = race;
+        self
+    }
+
+    /// Defaults to the standard array, led by the class's primary ability
+    pub fn scores(mut self, scores: AbilityScores) -> Self {
+        self.scores = Some(scores);
+        self
+    }
+
+    pub fn class(mut self, class: impl Into<String>) -> Self {
+        self.class = Some(class.into());
+        self
+    }
+
+    pub fn level(mut self, level: i32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Extra gear carried in the inventory, on top of the class's starting equipment
+    pub fn item(mut self, item: Item) -> Self {
+        self.items.push(item);
+        self
+    }
+
+    /// Make the character
+    ///
+    /// With a class, first-level hit points are the full hit die plus the
+    /// constitution modifier, and every later level is rolled as on level-up.
+    /// The class's starting equipment is equipped.
+    pub fn build(self, registry: &ClassRegistry) -> Result<Character, GenerationError> {
+        if !(1..=MAX_LEVEL).contains(&self.level) {
+            return Err(GenerationError::InvalidLevel(self.level));
+        }
+        let class = match &self.class {
+            Some(name) => Some(
+                registry
+                    .get(name)
+                    .ok_or_else(|| GenerationError::Class(ClassError::UnknownClass(name.clone())))?,
+            ),
+            None => None,
+        };
+
+        let mut roller = SeededRoller::new(self.seed);
+        let mut stats = match self.scores {
+            Some(AbilityScores::Rolled) => roll_ability_scores(&mut roller),
+            Some(AbilityScores::StandardArray(priority)) => standard_array(&priority),
+            Some(AbilityScores::PointBuy(stats)) => {
+                validate_point_buy(&stats)?;
+                stats
+            }
+            Some(AbilityScores::Fixed(stats)) => stats,
+            None => standard_array(&class.map(|c| vec![c.primary_ability]).unwrap_or_default()),
+        };
+        apply_racial_bonuses(&mut stats, &self.race);
+
+        let mut character = Character::new(self.name, self.race.race_type(), stats);
+        character.set_race(&self.race);
+        character.reset_movement();
+        for &skill in &self.race.skills {
+            character.train_skill(skill, Training::Proficient);
+        }
+        character.proficiencies.extend(self.race.proficiencies.iter().cloned());
+        // Ids are drawn from the seed too, rather than the thread's generator
+        let mut id_rng = ChaCha12Rng::seed_from_u64(self.seed);
+        let mut next_id = || Builder::from_random_bytes(id_rng.gen()).into_uuid().to_string();
+        character.id = next_id();
+
+        let Some(class) = class else {
+            character.level = self.level;
+            character.experience = experience_for_level(self.level);
+            return Ok(character);
+        };
+        let first_level = (class.hit_die + ability_modifier(character.stats.constitution)).max(1);
+        character.health.maximum = first_level;
+        character.health.current = first_level;
+        character.start_class(&class.name, registry).map_err(GenerationError::Class)?;
+        character.experience = experience_for_level(self.level);
+        while character.level < self.level {
+            character
+                .level_up(&class.name, registry, &mut roller)
+                .map_err(GenerationError::Class)?;
+        }
+
+        for starting in &class.starting_equipment {
+            let mut item = starting.to_item();
+            item.id = next_id();
+            let id = item.id.clone();
+            let equipped = character
+                .add_to_inventory(item)
+                .map_err(|e| e.to_string())
+                .and_then(|_| character.equip_item(&id));
+            if let Err(reason) = equipped {
+                return Err(GenerationError::Equipment { item: starting.name.clone(), reason });
+            }
+        }
+        for item in self.items {
+            let name = item.name.clone();
+            character
+                .add_to_inventory(item)
+                .map_err(|e| GenerationError::Equipment { item: name, reason: e.to_string() })?;
+        }
+        Ok(character)
+    }
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::ScoreOutOfRange { ability, score } => write!(
+                f,
+                "{:?} {} is outside point buy's {}-{}",
+                ability, score, POINT_BUY_RANGE.0, POINT_BUY_RANGE.1
+            ),
+            GenerationError::OverBudget { cost } => {
+                write!(f, "point buy costs {}, over the budget of {}", cost, POINT_BUY_BUDGET)
+            }
+            GenerationError::InvalidLevel(level) => write!(f, "level {} is not between 1 and {}", level, MAX_LEVEL),
+            GenerationError::Class(err) => write!(f, "{}", err),
+            GenerationError::Equipment { item, reason } => write!(f, "could not equip {}: {}", item, reason),
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dice::ScriptedRoller, item::EquipmentSlot};
+
+    #[test]
+    fn test_score_methods() {
+        // The lowest of each four dice is dropped
+        let mut roller = ScriptedRoller::new([6, 1, 5, 4, 3, 3, 3, 3]);
+        assert_eq!(roll_ability_score(&mut roller), 15);
+        assert_eq!(roll_ability_score(&mut roller), 9);
+
+        let stats = standard_array(&[Ability::Wisdom, Ability::Constitution]);
+        assert_eq!((stats.wisdom, stats.constitution, stats.strength, stats.charisma), (15, 14, 13, 8));
+
+        let spread = CharacterStats::from_scores([15, 15, 15, 8, 8, 8]);
+        assert_eq!(validate_point_buy(&spread), Ok(27));
+        let greedy = CharacterStats::from_scores([15, 15, 15, 9, 8, 8]);
+        assert_eq!(validate_point_buy(&greedy), Err(GenerationError::OverBudget { cost: 28 }));
+        let dumped = CharacterStats::from_scores([15, 15, 15, 8, 8, 7]);
+        assert_eq!(
+            validate_point_buy(&dumped).unwrap_err().to_string(),
+            "Charisma 7 is outside point buy's 8-15"
+        );
+
+        let mut scores = CharacterStats::from_scores([10; 6]);
+        apply_racial_bonuses(&mut scores, RaceDefinition::builtin(&RaceType::Orc));
+        assert_eq!((scores.strength, scores.constitution, scores.dexterity), (12, 11, 10));
+    }
+
+    #[test]
+    fn test_builder() {
+        let registry = ClassRegistry::with_defaults();
+        let build = |seed| {
+            CharacterBuilder::new("Brann", RaceType::Dwarf, seed)
+                .scores(AbilityScores::Rolled)
+                .class("Fighter")
+                .level(5)
+                .build(&registry)
+                .unwrap()
+        };
+        let fighter = build(7);
+        let again = build(7);
+        assert_eq!(fighter.id, again.id);
+        assert_eq!(fighter.equipment[&EquipmentSlot::Chest].id, again.equipment[&EquipmentSlot::Chest].id);
+        assert_eq!(fighter.stats.strength, again.stats.strength);
+        assert_eq!(fighter.health.maximum, again.health.maximum);
+
+        assert_eq!((fighter.level, fighter.class_level("Fighter")), (5, 5));
+        assert_eq!(fighter.attacks_per_action(), 2);
+        assert_eq!(fighter.equipment[&EquipmentSlot::MainHand].name, "Longsword");
+        assert_eq!(fighter.equipment[&EquipmentSlot::Chest].name, "Chain Mail");
+        // Trained with the class's gear: proficient attacks, no armor penalty
+        assert_eq!(fighter.weapon_proficiency_bonus(EquipmentSlot::MainHand), 3);
+        assert!(fighter.untrained_armor().is_none());
+
+        // Standard array led by dexterity, plus the elf's bonuses; 8 + 1 hit points at first level
+        let rogue = CharacterBuilder::new("Vex", RaceType::Elf, 1)
+            .class("Rogue")
+            .build(&registry)
+            .unwrap();
+        assert_eq!((rogue.stats.dexterity, rogue.stats.intelligence), (17, 13));
+        assert_eq!(rogue.health.maximum, 9);
+        assert_eq!(rogue.equipment[&EquipmentSlot::OffHand].name, "Dagger");
+
+        let over = CharacterBuilder::new("Greedy", RaceType::Human, 1)
+            .scores(AbilityScores::PointBuy(CharacterStats::from_scores([15; 6])))
+            .build(&registry);
+        assert!(matches!(over, Err(GenerationError::OverBudget { cost: 54 })));
+    }
+}
@@ -3,13 +3,14 @@ use std::cmp::Ordering;
 use serde::{Deserialize, Serialize};
 use crate::HexPosition;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "GridData", from = "GridData")]
 pub struct HexGrid {
     cells: HashMap<HexPosition, Cell>,
     size: (i32, i32), // width, height
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cell {
     pub position: HexPosition,
     pub terrain: TerrainType,
@@ -29,6 +30,65 @@ pub enum TerrainType {
     Lava,
 }
 
+/// Movement costs that replace a terrain's usual base cost for one creature,
+/// such as a dwarf crossing rough ground at the plain rate
+pub type TerrainCosts = HashMap<TerrainType, i32>;
+
+impl TerrainType {
+    /// Movement points to enter a hex of this terrain, before elevation
+    pub fn base_cost(&self) -> i32 {
+        match self {
+            TerrainType::Plain => 1,
+            TerrainType::Rough => 2,
+            TerrainType::Water => 3,
+            TerrainType::Wall => i32::MAX,
+            TerrainType::Sand => 2,
+            TerrainType::Snow => 2,
+            TerrainType::Swamp => 3,
+            TerrainType::Lava => i32::MAX,
+        }
+    }
+}
+
+impl Cell {
+    /// Cost of entering this cell with some terrain costs overridden
+    ///
+    /// Impassable terrain stays impassable, and any surcharge for a steep
+    /// neighbour is kept.
+    pub fn movement_cost_with(&self, costs: &TerrainCosts) -> i32 {
+        match costs.get(&self.terrain) {
+            Some(&cost) if self.movement_cost != i32::MAX => {
+                (self.movement_cost - self.terrain.base_cost() + cost).max(0)
+            }
+            _ => self.movement_cost,
+        }
+    }
+}
+
+/// How a grid is saved: a list of cells, since JSON maps need string keys
+#[derive(Serialize, Deserialize)]
+struct GridData {
+    size: (i32, i32),
+    cells: Vec<Cell>,
+}
+
+impl From<HexGrid> for GridData {
+    fn from(grid: HexGrid) -> Self {
+        let mut cells: Vec<Cell> = grid.cells.into_values().collect();
+        cells.sort_by_key(|c| (c.position.q, c.position.r, c.position.z));
+        Self { size: grid.size, cells }
+    }
+}
+
+impl From<GridData> for HexGrid {
+    fn from(data: GridData) -> Self {
+        Self {
+            cells: data.cells.into_iter().map(|c| (c.position, c)).collect(),
+            size: data.size,
+        }
+    }
+}
+
 #[derive(Eq, PartialEq)]
 struct Node {
     position: HexPosition,
@@ -66,17 +126,7 @@ impl HexGrid {
     pub fn add_cell(&mut self, mut position: HexPosition, terrain: TerrainType, elevation: i32) {
         position.z = elevation;
 
-        // Calculate base movement cost
-        let base_cost = match terrain {
-            TerrainType::Plain => 1,
-            TerrainType::Rough => 2,
-            TerrainType::Water => 3,
-            TerrainType::Wall => i32::MAX,
-            TerrainType::Sand => 2,
-            TerrainType::Snow => 2,
-            TerrainType::Swamp => 3,
-            TerrainType::Lava => i32::MAX,
-        };
+        let base_cost = terrain.base_cost();
 
         // Add extra cost for significant elevation changes
         let movement_cost = if let Some(neighbor_cells) = self.get_neighbors(position.clone())
@@ -179,6 +229,23 @@ impl HexGrid {
     }
 
     pub fn find_path(&self, start: HexPosition, goal: HexPosition) -> Option<Vec<HexPosition>> {
+        self.find_path_with(start, goal, &TerrainCosts::new())
+    }
+
+    /// Cheapest path for a creature with its own terrain costs
+    pub fn find_path_with(&self, start: HexPosition, goal: HexPosition, costs: &TerrainCosts) -> Option<Vec<HexPosition>> {
+        self.find_path_avoiding(start, goal, costs, |_| false)
+    }
+
+    /// Cheapest path that never steps onto a hex for which `blocked` returns true,
+    /// such as one held by another creature
+    pub fn find_path_avoiding(
+        &self,
+        start: HexPosition,
+        goal: HexPosition,
+        costs: &TerrainCosts,
+        blocked: impl Fn(&HexPosition) -> bool,
+    ) -> Option<Vec<HexPosition>> {
         if !self.is_in_bounds(&start) || !self.is_in_bounds(&goal) {
             return None;
         }
@@ -206,7 +273,7 @@ impl HexGrid {
             closed_set.insert(current.position.clone());
 
             for neighbor in self.get_neighbors(current.position.clone()) {
-                if closed_set.contains(&neighbor) {
+                if closed_set.contains(&neighbor) || blocked(&neighbor) {
                     continue;
                 }
 
@@ -215,12 +282,13 @@ impl HexGrid {
                     None => continue,
                 };
 
-                if neighbor_cell.movement_cost == i32::MAX {
+                let movement_cost = neighbor_cell.movement_cost_with(costs);
+                if movement_cost == i32::MAX {
                     continue;
                 }
 
                 let tentative_g_score = g_score.get(&current.position).unwrap() + 
-                    neighbor_cell.movement_cost + 
+                    movement_cost + 
                     self.elevation_cost(&current.position, &neighbor);
 
                 if !g_score.contains_key(&neighbor) || 
@@ -243,6 +311,25 @@ impl HexGrid {
         None
     }
 
+    /// Total movement cost of walking a path, using the same costs as `find_path`
+    pub fn path_cost(&self, path: &[HexPosition]) -> Option<i32> {
+        self.path_cost_with(path, &TerrainCosts::new())
+    }
+
+    /// Total cost of a path for a creature with its own terrain costs
+    pub fn path_cost_with(&self, path: &[HexPosition], costs: &TerrainCosts) -> Option<i32> {
+        let mut total: i32 = 0;
+        for step in path.windows(2) {
+            let movement_cost = self.cells.get(&step[1])?.movement_cost_with(costs);
+            let elevation = self.elevation_cost(&step[0], &step[1]);
+            if movement_cost == i32::MAX || elevation == i32::MAX {
+                return None;
+            }
+            total = total.checked_add(movement_cost + elevation)?;
+        }
+        Some(total)
+    }
+
     fn elevation_cost(&self, from: &HexPosition, to: &HexPosition) -> i32 {
         let from_cell = match self.cells.get(from) {
             Some(cell) => cell,
@@ -299,6 +386,15 @@ impl HexGrid {
         path
     }
 
+    /// Whether nothing between two hexes blocks sight; only walls do
+    pub fn has_line_of_sight(&self, from: HexPosition, to: HexPosition) -> bool {
+        let line = from.line_to(&to);
+        line.iter()
+            .skip(1)
+            .take(line.len().saturating_sub(2))
+            .all(|hex| !matches!(self.get_cell(hex), Some(cell) if cell.terrain == TerrainType::Wall))
+    }
+
     pub fn get_cell(&self, position: &HexPosition) -> Option<&Cell> {
         self.cells.get(position)
     }
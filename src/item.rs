@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use crate::{dice::Expr, race::RaceDefinition, skill::Proficiency};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum EquipmentSlot {
@@ -56,7 +57,8 @@ pub enum RaceType {
     Elf,
     Dwarf,
     Orc,
-    // Add more races as needed
+    /// A race defined in data, by name; see `race::RaceDefinition`
+    Custom(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -68,8 +70,13 @@ pub struct Item {
     pub value: i32,
     pub weight: f32,
     pub stats: Option<ItemStats>,
-    pub allowed_races: HashSet<RaceType>,
+    /// Races that may equip the item; `None` means any race, custom ones included
+    #[serde(default)]
+    pub allowed_races: Option<HashSet<RaceType>>,
     pub description: String,
+    /// Training needed to use the item well; see `Character::is_proficient_with`
+    #[serde(default)]
+    pub requires: Option<Proficiency>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -82,6 +89,9 @@ pub struct ItemStats {
     pub charisma_bonus: i32,
     pub armor: i32,
     pub damage: Option<WeaponDamage>,
+    /// Resistances, vulnerabilities and immunities granted while equipped
+    #[serde(default)]
+    pub defenses: DamageDefenses,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -89,14 +99,66 @@ pub struct WeaponDamage {
     pub min_damage: i32,
     pub max_damage: i32,
     pub damage_type: DamageType,
+    /// Range in hexes for ranged weapons; melee weapons use `None`
+    #[serde(default)]
+    pub range: Option<i32>,
+    /// Finesse weapons use the better of strength and dexterity
+    #[serde(default)]
+    pub finesse: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DamageType {
     Slashing,
     Piercing,
     Blunt,
     Magic,
+    Fire,
+    Cold,
+    Lightning,
+    Poison,
+    Necrotic,
+    Radiant,
+}
+
+/// How a creature's body or gear reacts to each damage type
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DamageDefenses {
+    #[serde(default)]
+    pub resistances: HashSet<DamageType>,
+    #[serde(default)]
+    pub vulnerabilities: HashSet<DamageType>,
+    #[serde(default)]
+    pub immunities: HashSet<DamageType>,
+}
+
+impl DamageDefenses {
+    pub fn is_empty(&self) -> bool {
+        self.resistances.is_empty() && self.vulnerabilities.is_empty() && self.immunities.is_empty()
+    }
+
+    /// Combine with another source; sets are unioned
+    pub fn merge(&mut self, other: &DamageDefenses) {
+        self.resistances.extend(other.resistances.iter().copied());
+        self.vulnerabilities.extend(other.vulnerabilities.iter().copied());
+        self.immunities.extend(other.immunities.iter().copied());
+    }
+
+    /// Damage remaining after immunity, resistance (halved, rounded down)
+    /// and vulnerability (doubled) are applied, in that order
+    pub fn apply(&self, damage: i32, damage_type: DamageType) -> i32 {
+        if self.immunities.contains(&damage_type) {
+            return 0;
+        }
+        let mut damage = damage;
+        if self.resistances.contains(&damage_type) {
+            damage /= 2;
+        }
+        if self.vulnerabilities.contains(&damage_type) {
+            damage *= 2;
+        }
+        damage
+    }
 }
 
 impl Item {
@@ -104,7 +166,7 @@ impl Item {
         name: String,
         equipment_type: EquipmentType,
         stats: ItemStats,
-        allowed_races: HashSet<RaceType>,
+        allowed_races: Option<HashSet<RaceType>>,
         level_req: i32,
     ) -> Self {
         Self {
@@ -117,6 +179,7 @@ impl Item {
             stats: Some(stats),
             allowed_races,
             description: String::new(), // Set appropriate description
+            requires: None,
         }
     }
 
@@ -133,13 +196,14 @@ impl Item {
             value: 0,
             weight: 0.1,
             stats: None,
-            allowed_races: RaceType::iter_all().collect(), // Available to all races
+            allowed_races: None, // Available to all races
             description,
+            requires: None,
         }
     }
 
     pub fn can_equip(&self, slot: &EquipmentSlot, race: &RaceType) -> bool {
-        if !self.allowed_races.contains(race) {
+        if self.allowed_races.as_ref().is_some_and(|races| !races.contains(race)) {
             return false;
         }
 
@@ -163,7 +227,47 @@ impl Item {
     }
 }
 
+impl ConsumableType {
+    /// Hit points restored on use, for consumables that heal
+    pub fn healing(&self) -> Option<Expr> {
+        match self {
+            ConsumableType::HealthPotion => Some(Expr::parse("2d4+2").expect("valid dice notation")),
+            _ => None,
+        }
+    }
+
+    /// Mana restored on use, for consumables that replenish spellcasting
+    pub fn mana(&self) -> Option<Expr> {
+        match self {
+            ConsumableType::ManaPotion => Some(Expr::parse("2d4+2").expect("valid dice notation")),
+            _ => None,
+        }
+    }
+}
+
+impl WeaponDamage {
+    pub fn is_ranged(&self) -> bool {
+        self.range.is_some()
+    }
+}
+
 impl RaceType {
+    /// Innate damage defenses of each race
+    pub fn damage_defenses(&self) -> DamageDefenses {
+        RaceDefinition::builtin(self).defenses.clone()
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            RaceType::Human => "Human",
+            RaceType::Elf => "Elf",
+            RaceType::Dwarf => "Dwarf",
+            RaceType::Orc => "Orc",
+            RaceType::Custom(name) => name,
+        }
+    }
+
+    /// The built-in races
     pub fn iter_all() -> impl Iterator<Item = RaceType> {
         vec![
             RaceType::Human,
@@ -200,14 +304,49 @@ mod tests {
                     min_damage: 2,
                     max_damage: 6,
                     damage_type: DamageType::Slashing,
+                    range: None,
+                    finesse: false,
                 }),
+                defenses: DamageDefenses::default(),
             },
-            allowed_races,
+            Some(allowed_races),
             1,
         );
 
         assert!(sword.can_equip(&EquipmentSlot::MainHand, &RaceType::Human));
         assert!(!sword.can_equip(&EquipmentSlot::MainHand, &RaceType::Orc));
+
+        let unrestricted = Item { allowed_races: None, ..sword.clone() };
+        assert!(unrestricted.can_equip(&EquipmentSlot::MainHand, &RaceType::Custom("Goblin".to_string())));
+    }
+
+    #[test]
+    fn test_allowed_races_from_old_saves() {
+        let mut old = serde_json::to_value(Item::new_consumable(
+            "Rations".to_string(),
+            ConsumableType::Food,
+            String::new(),
+        ))
+        .unwrap();
+        let old = old.as_object_mut().unwrap();
+        old.remove("requires");
+
+        // An empty list always meant nobody could equip the item
+        old.insert("allowed_races".to_string(), serde_json::json!([]));
+        let item: Item = serde_json::from_value(old.clone().into()).unwrap();
+        assert_eq!(item.allowed_races, Some(HashSet::new()));
+
+        old.insert("allowed_races".to_string(), serde_json::json!(["Dwarf"]));
+        let item: Item = serde_json::from_value(old.clone().into()).unwrap();
+        assert_eq!(item.allowed_races, Some(HashSet::from([RaceType::Dwarf])));
+
+        let json = serde_json::to_string(&item).unwrap();
+        assert_eq!(serde_json::from_str::<Item>(&json).unwrap().allowed_races, item.allowed_races);
+
+        // Left out, the item is open to every race
+        old.remove("allowed_races");
+        let item: Item = serde_json::from_value(old.clone().into()).unwrap();
+        assert_eq!(item.allowed_races, None);
     }
 
     #[test]
@@ -223,4 +362,17 @@ mod tests {
             ItemType::Consumable(ConsumableType::HealthPotion)
         ));
     }
+
+    #[test]
+    fn test_damage_defenses() {
+        let mut defenses = DamageDefenses::default();
+        defenses.resistances.insert(DamageType::Slashing);
+        defenses.vulnerabilities.insert(DamageType::Radiant);
+        defenses.immunities.insert(DamageType::Poison);
+
+        assert_eq!(defenses.apply(9, DamageType::Slashing), 4);
+        assert_eq!(defenses.apply(9, DamageType::Radiant), 18);
+        assert_eq!(defenses.apply(9, DamageType::Poison), 0);
+        assert_eq!(defenses.apply(9, DamageType::Fire), 9);
+    }
 }
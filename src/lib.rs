@@ -6,13 +6,23 @@
 use serde::{Deserialize, Serialize};
 
 pub mod character;
+pub mod check;
+pub mod class;
 pub mod combat;
 pub mod grid;
 pub mod dice;
+pub mod effect;
+pub mod encumbrance;
+pub mod generation;
 pub mod item;
 pub mod map;
+pub mod race;
 pub mod template;
 pub mod structure;
+pub mod simulation;
+pub mod skill;
+pub mod spell;
+pub mod area;
 
 // Re-export commonly used types
 pub use character::Character;
@@ -47,19 +57,67 @@ impl HexPosition {
 
     /// Calculate the 3D distance between two hex positions
     pub fn distance(&self, other: &HexPosition) -> i32 {
+        let height_difference = (self.z - other.z).abs();
+        self.planar_distance(other) + height_difference
+    }
+
+    /// Distance in hexes ignoring the vertical coordinate
+    pub fn planar_distance(&self, other: &HexPosition) -> i32 {
         let (x1, y1, z1) = self.cube_coords();
         let (x2, y2, z2) = other.cube_coords();
-        
-        // Manhattan distance in 3D cube coordinates plus vertical distance
-        let planar_distance = ((x1 - x2).abs() + (y1 - y2).abs() + (z1 - z2).abs()) / 2;
-        let height_difference = (self.z - other.z).abs();
-        
-        planar_distance + height_difference
+
+        // Manhattan distance in 3D cube coordinates
+        ((x1 - x2).abs() + (y1 - y2).abs() + (z1 - z2).abs()) / 2
     }
+
+    /// The adjacent hex in a direction, on the same level
+    pub fn neighbor(&self, direction: Direction) -> HexPosition {
+        let (dq, dr) = direction.offset();
+        HexPosition::new(self.q + dq, self.r + dr, self.z)
+    }
+
+    /// Hexes on the straight line to `other`, both ends included, on this hex's level
+    pub fn line_to(&self, other: &HexPosition) -> Vec<HexPosition> {
+        let steps = self.planar_distance(other);
+        if steps == 0 {
+            return vec![*self];
+        }
+
+        // Nudge off hex edges so ties round consistently
+        let (x1, y1, z1) = self.cube_coords();
+        let (x2, y2, z2) = other.cube_coords();
+        let start = (x1 as f64 + 1e-6, y1 as f64 + 2e-6, z1 as f64 - 3e-6);
+        let end = (x2 as f64 + 1e-6, y2 as f64 + 2e-6, z2 as f64 - 3e-6);
+
+        (0..=steps)
+            .map(|i| {
+                let t = i as f64 / steps as f64;
+                let (x, y, z) = (
+                    start.0 + (end.0 - start.0) * t,
+                    start.1 + (end.1 - start.1) * t,
+                    start.2 + (end.2 - start.2) * t,
+                );
+                let (q, r) = cube_round(x, y, z);
+                HexPosition::new(q, r, self.z)
+            })
+            .collect()
+    }
+}
+
+/// Round fractional cube coordinates to the containing hex, returning (q, r)
+fn cube_round(x: f64, y: f64, z: f64) -> (i32, i32) {
+    let (mut rx, ry, mut rz) = (x.round(), y.round(), z.round());
+    let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+    if dx > dy && dx > dz {
+        rx = -ry - rz;
+    } else if dy <= dz {
+        rz = -rx - ry;
+    }
+    (rx as i32, rz as i32)
 }
 
 /// Represents a cardinal direction in the hex grid
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Direction {
     North,
     NorthEast,
@@ -69,6 +127,31 @@ pub enum Direction {
     NorthWest,
 }
 
+impl Direction {
+    pub fn all() -> [Direction; 6] {
+        [
+            Direction::North,
+            Direction::NorthEast,
+            Direction::SouthEast,
+            Direction::South,
+            Direction::SouthWest,
+            Direction::NorthWest,
+        ]
+    }
+
+    /// Axial (q, r) step of one hex in this direction
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::NorthEast => (1, -1),
+            Direction::SouthEast => (1, 0),
+            Direction::South => (0, 1),
+            Direction::SouthWest => (-1, 1),
+            Direction::NorthWest => (-1, 0),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -94,4 +177,13 @@ mod tests {
         // Distance should include both planar and vertical components
         assert_eq!(pos1.distance(&pos2), 4); // 2 steps in plane + 2 steps up
     }
+
+    #[test]
+    fn test_hex_line() {
+        let start = HexPosition::new_2d(0, 0);
+        let line = start.line_to(&HexPosition::new_2d(3, -3));
+        assert_eq!(line.len(), 4);
+        assert_eq!(line[1], start.neighbor(Direction::NorthEast));
+        assert!(line.windows(2).all(|w| w[0].distance(&w[1]) == 1));
+    }
 }
@@ -0,0 +1,257 @@
+//! Race definitions: ability bonuses, speed, size, senses and other traits
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character,
+    character::Ability,
+    grid::{TerrainCosts, TerrainType},
+    item::{DamageDefenses, RaceType},
+    skill::{Proficiency, Skill},
+};
+
+/// The races shipped in `races/`, one per built-in `RaceType`
+const DEFAULT_RACES: [&str; 4] = [
+    include_str!("../races/human.yaml"),
+    include_str!("../races/elf.yaml"),
+    include_str!("../races/dwarf.yaml"),
+    include_str!("../races/orc.yaml"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+pub enum Size {
+    Tiny,
+    Small,
+    #[default]
+    Medium,
+    Large,
+    Huge,
+    Gargantuan,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaceDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Added to the ability scores of a newly generated character
+    #[serde(default)]
+    pub ability_bonuses: HashMap<Ability, i32>,
+    /// Base speed in hexes per turn
+    #[serde(default = "default_speed")]
+    pub speed: i32,
+    #[serde(default)]
+    pub size: Size,
+    /// How far the race sees in darkness, in hexes; zero for none
+    #[serde(default)]
+    pub darkvision: i32,
+    #[serde(default)]
+    pub defenses: DamageDefenses,
+    #[serde(default)]
+    pub terrain_affinities: Vec<TerrainAffinity>,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Skills every member of the race is trained in
+    #[serde(default)]
+    pub skills: Vec<Skill>,
+    #[serde(default)]
+    pub proficiencies: Vec<Proficiency>,
+}
+
+/// A terrain the race crosses at a different cost than usual
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerrainAffinity {
+    pub terrain: TerrainType,
+    /// Replaces the terrain's base cost; 1 treats it as open ground
+    pub cost: i32,
+}
+
+fn default_speed() -> i32 {
+    6
+}
+
+impl Default for RaceDefinition {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            description: String::new(),
+            ability_bonuses: HashMap::new(),
+            speed: default_speed(),
+            size: Size::default(),
+            darkvision: 0,
+            defenses: DamageDefenses::default(),
+            terrain_affinities: Vec::new(),
+            languages: Vec::new(),
+            skills: Vec::new(),
+            proficiencies: Vec::new(),
+        }
+    }
+}
+
+impl RaceDefinition {
+    /// The definition of a built-in race; other races get plain defaults
+    pub fn builtin(race: &RaceType) -> &'static RaceDefinition {
+        static BUILTIN: OnceLock<(RaceRegistry, RaceDefinition)> = OnceLock::new();
+        let (registry, generic) = BUILTIN.get_or_init(|| (RaceRegistry::with_defaults(), RaceDefinition::default()));
+        match race {
+            RaceType::Custom(_) => generic,
+            _ => registry.get(race.name()).unwrap_or(generic),
+        }
+    }
+
+    /// The matching built-in variant, or `RaceType::Custom` for anything else
+    pub fn race_type(&self) -> RaceType {
+        RaceType::iter_all()
+            .find(|race| race.name() == self.name)
+            .unwrap_or_else(|| RaceType::Custom(self.name.clone()))
+    }
+
+    pub fn terrain_costs(&self) -> TerrainCosts {
+        self.terrain_affinities.iter().map(|a| (a.terrain, a.cost)).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RaceRegistry {
+    races: HashMap<String, RaceDefinition>,
+}
+
+impl RaceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the human, elf, dwarf and orc loaded
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::new();
+        for yaml in DEFAULT_RACES {
+            registry.load_race(yaml).expect("built-in races are valid");
+        }
+        registry
+    }
+
+    /// Add a race from YAML, replacing any with the same name
+    pub fn load_race(&mut self, yaml: &str) -> Result<(), serde_yaml::Error> {
+        let race: RaceDefinition = serde_yaml::from_str(yaml)?;
+        self.races.insert(race.name.clone(), race);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RaceDefinition> {
+        self.races.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.races.keys().map(|name| name.as_str())
+    }
+}
+
+impl Character {
+    /// Traits of the character's race
+    pub fn race_definition(&self) -> &RaceDefinition {
+        self.race_traits
+            .as_ref()
+            .unwrap_or_else(|| RaceDefinition::builtin(&self.race))
+    }
+
+    /// Become a member of `definition`'s race, taking its speed
+    ///
+    /// Ability bonuses, skills and proficiencies are left to character generation. Unchanged built-in
+    /// races are looked up rather than stored on the character.
+    pub fn set_race(&mut self, definition: &RaceDefinition) {
+        self.race = definition.race_type();
+        self.race_traits = if RaceDefinition::builtin(&self.race) == definition {
+            None
+        } else {
+            Some(definition.clone())
+        };
+        self.movement.speed = definition.speed;
+        self.movement.remaining = self.movement.remaining.min(definition.speed);
+    }
+
+    pub fn size(&self) -> Size {
+        self.race_definition().size
+    }
+
+    pub fn darkvision(&self) -> i32 {
+        self.race_definition().darkvision
+    }
+
+    pub fn speaks(&self, language: &str) -> bool {
+        self.race_definition().languages.iter().any(|l| l.eq_ignore_ascii_case(language))
+    }
+
+    /// Terrain costs to path with in place of the grid's usual ones
+    pub fn terrain_costs(&self) -> TerrainCosts {
+        self.race_definition().terrain_costs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HexGrid, HexPosition, character::CharacterStats, item::DamageType};
+
+    fn character(race: RaceType) -> Character {
+        let stats = CharacterStats::from_scores([10; 6]);
+        Character::new("Traveller".to_string(), race, stats)
+    }
+
+    #[test]
+    fn test_builtin_races() {
+        let registry = RaceRegistry::with_defaults();
+        let mut names: Vec<&str> = registry.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["Dwarf", "Elf", "Human", "Orc"]);
+
+        let mut dwarf = character(RaceType::Dwarf);
+        assert_eq!(dwarf.movement.speed, 5);
+        assert!(dwarf.speaks("dwarvish") && !dwarf.speaks("Elvish"));
+        assert_eq!(dwarf.take_damage(10, DamageType::Poison), 5);
+
+        // A row of rough ground costs a dwarf no more than open plain
+        let mut grid = HexGrid::with_size(4, 1);
+        for q in 0..4 {
+            grid.add_cell(HexPosition::new_2d(q, 0), TerrainType::Rough, 0);
+        }
+        let path: Vec<HexPosition> = (0..4).map(|q| HexPosition::new_2d(q, 0)).collect();
+        assert_eq!(grid.path_cost_with(&path, &dwarf.terrain_costs()), Some(3));
+        assert_eq!(grid.path_cost_with(&path, &character(RaceType::Elf).terrain_costs()), Some(6));
+    }
+
+    #[test]
+    fn test_homebrew_race() {
+        let mut registry = RaceRegistry::with_defaults();
+        registry
+            .load_race(
+                r#"
+name: "Gnome"
+ability_bonuses: { Intelligence: 2 }
+speed: 5
+size: Small
+darkvision: 12
+defenses: { resistances: [Magic] }
+terrain_affinities:
+  - { terrain: Swamp, cost: 1 }
+languages: ["Common", "Gnomish"]
+"#,
+            )
+            .unwrap();
+
+        let mut gnome = character(RaceType::Human);
+        gnome.set_race(registry.get("Gnome").unwrap());
+        assert_eq!(gnome.race, RaceType::Custom("Gnome".to_string()));
+        assert_eq!((gnome.size(), gnome.darkvision(), gnome.movement.speed), (Size::Small, 12, 5));
+        assert_eq!(gnome.terrain_costs().get(&TerrainType::Swamp), Some(&1));
+
+        // The definition travels with the character
+        let restored: Character = serde_json::from_str(&serde_json::to_string(&gnome).unwrap()).unwrap();
+        assert!(restored.speaks("Gnomish"));
+        assert_eq!(restored.damage_defenses().resistances.len(), 1);
+
+        // Built-in races are looked up again rather than saved
+        gnome.set_race(registry.get("Orc").unwrap());
+        assert_eq!((gnome.race.clone(), gnome.race_traits.is_none()), (RaceType::Orc, true));
+    }
+}
@@ -0,0 +1,424 @@
+//! Headless batch simulation of AI-driven encounters for balancing
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character, Combat, HexGrid,
+    combat::{AggressiveMelee, CombatAi, CombatEvent, CombatLog, KitingRanged, VictoryCondition},
+    effect::EffectTick,
+    item::EquipmentSlot,
+};
+
+/// A side in a simulated encounter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Party {
+    pub name: String,
+    pub members: Vec<Character>,
+}
+
+impl Party {
+    pub fn new(name: String, members: Vec<Character>) -> Self {
+        Self { name, members }
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationConfig {
+    pub encounters: usize,
+    /// Encounters still running after this many rounds count as draws
+    pub max_rounds: u32,
+    /// Encounter `i` is fought with seed `seed + i`
+    pub seed: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            encounters: 1000,
+            max_rounds: 50,
+            seed: 0,
+        }
+    }
+}
+
+/// How one participant fared in a single encounter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParticipantOutcome {
+    pub party: String,
+    pub name: String,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    pub kills: u32,
+    pub survived: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct EncounterOutcome {
+    /// The last party standing; `None` for a draw
+    pub winner: Option<String>,
+    pub rounds: u32,
+    pub participants: Vec<ParticipantOutcome>,
+    pub log: CombatLog,
+}
+
+/// Averages for one participant across every encounter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticipantSummary {
+    pub party: String,
+    pub name: String,
+    pub average_damage_dealt: f64,
+    pub average_damage_taken: f64,
+    pub survival_rate: f64,
+    /// Number of encounters in which the participant scored each kill count
+    pub kills: BTreeMap<u32, usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub encounters: usize,
+    pub wins: BTreeMap<String, usize>,
+    pub draws: usize,
+    pub average_rounds: f64,
+    pub participants: Vec<ParticipantSummary>,
+}
+
+/// Runs many seeded encounters between parties, each member driven by an AI
+///
+/// Members with a ranged main-hand weapon kite; everyone else charges into melee.
+pub struct Simulator {
+    config: SimulationConfig,
+    parties: Vec<Party>,
+    grid: Option<HexGrid>,
+}
+
+impl Simulator {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            config,
+            parties: Vec::new(),
+            grid: None,
+        }
+    }
+
+    pub fn add_party(&mut self, party: Party) {
+        self.parties.push(party);
+    }
+
+    /// Fight on a grid, starting everyone from the positions in their character data
+    pub fn set_grid(&mut self, grid: HexGrid) {
+        self.grid = Some(grid);
+    }
+
+    pub fn run(&self) -> SimulationReport {
+        let outcomes: Vec<EncounterOutcome> = (0..self.config.encounters)
+            .map(|i| self.run_encounter(i))
+            .collect();
+        SimulationReport::from_outcomes(&outcomes)
+    }
+
+    /// Fight a single encounter with the seed for index `index`
+    pub fn run_encounter(&self, index: usize) -> EncounterOutcome {
+        let mut combat = Combat::with_seed(self.config.seed.wrapping_add(index as u64));
+        combat.set_undo_limit(0);
+        if let Some(grid) = &self.grid {
+            combat.set_grid(grid.clone());
+        }
+
+        // Stable ids keep encounters reproducible even if two parties share a file
+        let mut ais: HashMap<String, Box<dyn CombatAi>> = HashMap::new();
+        let mut roster = Vec::new();
+        for (p, party) in self.parties.iter().enumerate() {
+            for (m, member) in party.members.iter().enumerate() {
+                let mut character = member.clone();
+                character.id = format!("{}:{}", p, m);
+                ais.insert(character.id.clone(), behaviour_for(&character));
+                roster.push((character.id.clone(), party.name.clone(), character.name.clone()));
+                let id = character.id.clone();
+                combat.add_participant(character);
+                combat.set_team(&id, &party.name);
+            }
+        }
+
+        combat.add_victory_condition(VictoryCondition::LastTeamStanding);
+        while let Some(id) = combat.next_turn().map(|c| c.id.clone()) {
+            if combat.round() > self.config.max_rounds {
+                break;
+            }
+            if let Some(ai) = ais.get_mut(&id) {
+                // An illegal choice just forfeits the rest of the turn
+                let _ = combat.take_ai_turn(ai.as_mut());
+            }
+        }
+
+        let rounds = combat.round().min(self.config.max_rounds);
+        // Parties are all hostile to each other, so at most one can win
+        let winner = combat.outcome().and_then(|o| o.winners.first().cloned());
+        let tally = tally_log(combat.log());
+        let participants = roster
+            .into_iter()
+            .map(|(id, party, name)| {
+                let (dealt, taken, kills) = tally.get(&id).copied().unwrap_or_default();
+                ParticipantOutcome {
+                    party,
+                    name,
+                    damage_dealt: dealt,
+                    damage_taken: taken,
+                    kills,
+                    survived: combat.get_participant(&id).is_some_and(Character::is_alive),
+                }
+            })
+            .collect();
+
+        EncounterOutcome {
+            winner,
+            rounds,
+            participants,
+            log: combat.log().clone(),
+        }
+    }
+}
+
+fn behaviour_for(character: &Character) -> Box<dyn CombatAi> {
+    match character.weapon(EquipmentSlot::MainHand) {
+        Some(weapon) if weapon.is_ranged() => Box::new(KitingRanged::new()),
+        _ => Box::new(AggressiveMelee::new()),
+    }
+}
+
+/// Damage dealt, damage taken and kills per participant id
+///
+/// A kill goes to whoever last damaged the fallen participant.
+fn tally_log(log: &CombatLog) -> HashMap<String, (i32, i32, u32)> {
+    let mut tally: HashMap<String, (i32, i32, u32)> = HashMap::new();
+    let mut last_damaged_by: HashMap<&str, &str> = HashMap::new();
+    // Area and spell hits are logged after the attack or cast that caused them
+    let mut source: Option<&str> = None;
+
+    for entry in log.entries() {
+        let hit = match &entry.event {
+            CombatEvent::Attacked { attacker_id, defender_id, damage, .. } => {
+                Some((Some(attacker_id.as_str()), defender_id, *damage))
+            }
+            CombatEvent::AreaAttacked { source_id, .. } => {
+                source = Some(source_id);
+                None
+            }
+            CombatEvent::SpellCast { caster_id, .. } => {
+                source = Some(caster_id);
+                None
+            }
+            CombatEvent::AreaHit { id, damage, .. } | CombatEvent::SpellHit { id, damage, .. } => {
+                Some((source, id, *damage))
+            }
+            CombatEvent::EffectTicked { id, tick: EffectTick::Damaged { amount, .. } } => Some((None, id, *amount)),
+            CombatEvent::Died { id } => {
+                if let Some(killer) = last_damaged_by.get(id.as_str()) {
+                    tally.entry(killer.to_string()).or_default().2 += 1;
+                }
+                None
+            }
+            _ => None,
+        };
+
+        if let Some((dealer, target, damage)) = hit.filter(|&(_, _, damage)| damage > 0) {
+            tally.entry(target.clone()).or_default().1 += damage;
+            if let Some(dealer) = dealer {
+                tally.entry(dealer.to_string()).or_default().0 += damage;
+                last_damaged_by.insert(target, dealer);
+            }
+        }
+    }
+
+    tally
+}
+
+impl SimulationReport {
+    pub fn from_outcomes(outcomes: &[EncounterOutcome]) -> Self {
+        let encounters = outcomes.len();
+        let per_encounter = |total: f64| if encounters == 0 { 0.0 } else { total / encounters as f64 };
+
+        let mut wins = BTreeMap::new();
+        let mut draws = 0;
+        for outcome in outcomes {
+            match &outcome.winner {
+                Some(party) => *wins.entry(party.clone()).or_insert(0) += 1,
+                None => draws += 1,
+            }
+        }
+        let average_rounds = per_encounter(outcomes.iter().map(|o| o.rounds as f64).sum());
+
+        // Every encounter lists the same roster in the same order
+        let roster = outcomes.first().map_or(0, |o| o.participants.len());
+        let participants = (0..roster)
+            .map(|i| {
+                let results: Vec<&ParticipantOutcome> = outcomes.iter().map(|o| &o.participants[i]).collect();
+                let mut kills = BTreeMap::new();
+                for result in &results {
+                    *kills.entry(result.kills).or_insert(0) += 1;
+                }
+                ParticipantSummary {
+                    party: results[0].party.clone(),
+                    name: results[0].name.clone(),
+                    average_damage_dealt: per_encounter(results.iter().map(|r| r.damage_dealt as f64).sum()),
+                    average_damage_taken: per_encounter(results.iter().map(|r| r.damage_taken as f64).sum()),
+                    survival_rate: per_encounter(results.iter().filter(|r| r.survived).count() as f64),
+                    kills,
+                }
+            })
+            .collect();
+
+        Self {
+            encounters,
+            wins,
+            draws,
+            average_rounds,
+            participants,
+        }
+    }
+
+    /// Fraction of encounters won by a party
+    pub fn win_rate(&self, party: &str) -> f64 {
+        if self.encounters == 0 {
+            return 0.0;
+        }
+        self.wins.get(party).copied().unwrap_or(0) as f64 / self.encounters as f64
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Encounters: {}", self.encounters)?;
+        for party in self.wins.keys() {
+            writeln!(f, "  {} wins: {:.1}%", party, self.win_rate(party) * 100.0)?;
+        }
+        if self.encounters > 0 {
+            writeln!(f, "  draws: {:.1}%", self.draws as f64 / self.encounters as f64 * 100.0)?;
+        }
+        writeln!(f, "Average rounds: {:.2}", self.average_rounds)?;
+        writeln!(f)?;
+        writeln!(f, "{:<12} {:<16} {:>8} {:>8} {:>9}  kills", "party", "name", "dealt", "taken", "survived")?;
+        for p in &self.participants {
+            let kills: Vec<String> = p.kills.iter().map(|(k, n)| format!("{}:{}", k, n)).collect();
+            writeln!(
+                f,
+                "{:<12} {:<16} {:>8.2} {:>8.2} {:>8.1}%  {}",
+                p.party,
+                p.name,
+                p.average_damage_dealt,
+                p.average_damage_taken,
+                p.survival_rate * 100.0,
+                kills.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{character::CharacterStats, item::RaceType};
+
+    fn party(name: &str, strength: i32, size: usize) -> Party {
+        let stats = CharacterStats {
+            strength,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10,
+        };
+        let members = (0..size)
+            .map(|i| Character::new(format!("{} {}", name, i), RaceType::Human, stats.clone()))
+            .collect();
+        Party::new(name.to_string(), members)
+    }
+
+    #[test]
+    fn test_party_files() {
+        let yaml = serde_yaml::to_string(&party("Heroes", 14, 2)).unwrap();
+        let loaded = Party::from_yaml(&yaml).unwrap();
+        assert_eq!(loaded.members.len(), 2);
+        assert_eq!(loaded.members[1].name, "Heroes 1");
+
+        let json = serde_json::to_string(&loaded).unwrap();
+        assert_eq!(Party::from_json(&json).unwrap().name, "Heroes");
+    }
+
+    #[test]
+    fn test_tally_counts_area_and_spell_damage() {
+        use crate::{area::AreaShape, item::DamageType};
+
+        let mut log = CombatLog::new();
+        let events = [
+            CombatEvent::AreaAttacked {
+                source_id: "rogue".to_string(),
+                origin: crate::HexPosition::new_2d(0, 0),
+                shape: AreaShape::Burst { radius: 1 },
+                damage: 5,
+                damage_type: DamageType::Magic,
+            },
+            CombatEvent::AreaHit { id: "orc".to_string(), save_roll: None, saved: false, damage: 5 },
+            CombatEvent::AreaHit { id: "goblin".to_string(), save_roll: None, saved: false, damage: 5 },
+            CombatEvent::Died { id: "goblin".to_string() },
+            CombatEvent::SpellCast {
+                caster_id: "wizard".to_string(),
+                spell: "Fire Bolt".to_string(),
+                target: crate::HexPosition::new_2d(1, 0),
+            },
+            CombatEvent::SpellHit {
+                id: "orc".to_string(),
+                attack_roll: None,
+                save_roll: None,
+                landed: true,
+                damage: 8,
+                healed: 0,
+            },
+            CombatEvent::Died { id: "orc".to_string() },
+        ];
+        for event in events {
+            log.push(1, event);
+        }
+
+        let tally = tally_log(&log);
+        assert_eq!(tally["rogue"], (10, 0, 1));
+        assert_eq!(tally["wizard"], (8, 0, 1));
+        assert_eq!(tally["orc"], (0, 13, 0));
+        assert_eq!(tally["goblin"], (0, 5, 0));
+    }
+
+    #[test]
+    fn test_simulation_report() {
+        let config = SimulationConfig { encounters: 200, max_rounds: 30, seed: 42 };
+        let mut simulator = Simulator::new(config);
+        simulator.add_party(party("Strong", 18, 3));
+        simulator.add_party(party("Weak", 6, 2));
+
+        let report = simulator.run();
+        assert_eq!(report.encounters, 200);
+        assert_eq!(report.wins.values().sum::<usize>() + report.draws, 200);
+        assert!(report.win_rate("Strong") > report.win_rate("Weak"));
+        assert!(report.average_rounds >= 1.0);
+        assert_eq!(report.participants.len(), 5);
+        for p in &report.participants {
+            assert_eq!(p.kills.values().sum::<usize>(), 200);
+        }
+
+        // Same seed, same numbers
+        let again = simulator.run();
+        assert_eq!(again.to_json().unwrap(), report.to_json().unwrap());
+    }
+}
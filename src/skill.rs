@@ -0,0 +1,246 @@
+//! Skills, expertise and proficiency with weapons, armor and tools
+
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character,
+    character::Ability,
+    check::{Check, CheckResult},
+    dice::{self, DiceRoller, RollMode, RollOptions},
+    item::{EquipmentSlot, EquipmentType, Item, ItemType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Skill {
+    Acrobatics,
+    AnimalHandling,
+    Arcana,
+    Athletics,
+    Deception,
+    History,
+    Insight,
+    Intimidation,
+    Investigation,
+    Medicine,
+    Nature,
+    Perception,
+    Performance,
+    Persuasion,
+    Religion,
+    SleightOfHand,
+    Stealth,
+    Survival,
+}
+
+/// How well a character knows a skill
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Training {
+    /// Adds the proficiency bonus
+    Proficient,
+    /// Adds double the proficiency bonus
+    Expertise,
+}
+
+/// Gear a character can be trained to use
+///
+/// Items name what they need in `Item::requires`; using one without the
+/// training is allowed but penalised.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Proficiency {
+    LightArmor,
+    MediumArmor,
+    HeavyArmor,
+    SimpleWeapons,
+    MartialWeapons,
+    /// One weapon by item name, such as "Rapier"
+    Weapon(String),
+    /// A set of tools by name, such as "Thieves' Tools"
+    Tool(String),
+}
+
+impl Skill {
+    pub const ALL: [Skill; 18] = [
+        Skill::Acrobatics,
+        Skill::AnimalHandling,
+        Skill::Arcana,
+        Skill::Athletics,
+        Skill::Deception,
+        Skill::History,
+        Skill::Insight,
+        Skill::Intimidation,
+        Skill::Investigation,
+        Skill::Medicine,
+        Skill::Nature,
+        Skill::Perception,
+        Skill::Performance,
+        Skill::Persuasion,
+        Skill::Religion,
+        Skill::SleightOfHand,
+        Skill::Stealth,
+        Skill::Survival,
+    ];
+
+    /// The ability the skill is checked with
+    pub fn ability(self) -> Ability {
+        match self {
+            Skill::Athletics => Ability::Strength,
+            Skill::Acrobatics | Skill::SleightOfHand | Skill::Stealth => Ability::Dexterity,
+            Skill::Arcana | Skill::History | Skill::Investigation | Skill::Nature | Skill::Religion => {
+                Ability::Intelligence
+            }
+            Skill::AnimalHandling | Skill::Insight | Skill::Medicine | Skill::Perception | Skill::Survival => {
+                Ability::Wisdom
+            }
+            Skill::Deception | Skill::Intimidation | Skill::Performance | Skill::Persuasion => Ability::Charisma,
+        }
+    }
+}
+
+impl Training {
+    /// Multiple of the proficiency bonus added
+    pub fn multiplier(self) -> i32 {
+        match self {
+            Training::Proficient => 1,
+            Training::Expertise => 2,
+        }
+    }
+}
+
+impl Character {
+    pub fn skill_training(&self, skill: Skill) -> Option<Training> {
+        self.skills.get(&skill).copied()
+    }
+
+    /// Learn a skill; training never goes down, so expertise survives a later proficiency
+    pub fn train_skill(&mut self, skill: Skill, training: Training) {
+        let current = self.skills.entry(skill).or_insert(training);
+        *current = (*current).max(training);
+    }
+
+    /// 10 plus everything a check would add, for when nobody is actively rolling
+    pub fn passive_score(&self, skill: Skill) -> i32 {
+        10 + self.check_modifier(Check::Skill(skill))
+    }
+
+    pub fn passive_perception(&self) -> i32 {
+        self.passive_score(Skill::Perception)
+    }
+
+    /// Stealth against the observer's passive perception
+    pub fn sneak_past(&self, observer: &Character, roller: &mut dyn DiceRoller) -> CheckResult {
+        self.attempt(Check::Skill(Skill::Stealth), observer.passive_perception(), RollMode::Normal, roller)
+    }
+
+    /// An ability check using a set of tools, adding the proficiency bonus
+    /// if the character is trained with them
+    pub fn tool_check(&self, tool: &str, ability: Ability, dc: i32, roller: &mut dyn DiceRoller) -> CheckResult {
+        let check = Check::Ability(ability);
+        let trained = self.proficiencies.contains(&Proficiency::Tool(tool.to_string()));
+        let bonus = if trained { self.proficiency_bonus() } else { 0 };
+        let options = RollOptions { mode: self.check_mode(check, RollMode::Normal), ..Default::default() };
+        let roll = dice::roll_with(roller, 1, 20, self.check_modifier(check) + bonus, options);
+        CheckResult { check, success: roll.value >= dc, roll, dc }
+    }
+
+    /// Whether the character has the training an item asks for;
+    /// items that ask for none count as untrained gear
+    pub fn is_proficient_with(&self, item: &Item) -> bool {
+        match &item.requires {
+            Some(required) => {
+                self.proficiencies.contains(required)
+                    || self.proficiencies.contains(&Proficiency::Weapon(item.name.clone()))
+            }
+            None => false,
+        }
+    }
+
+    /// Worn armor the character is not trained for, which gives disadvantage
+    /// on attacks and on strength and dexterity checks and saves
+    pub fn untrained_armor(&self) -> Option<&Item> {
+        self.equipment.values().find(|item| {
+            let is_weapon = matches!(item.item_type, ItemType::Equipment(EquipmentType::Weapon(_)));
+            !is_weapon && item.requires.is_some() && !self.is_proficient_with(item)
+        })
+    }
+
+    /// Proficiency bonus added to attack rolls with the weapon in `slot`
+    pub fn weapon_proficiency_bonus(&self, slot: EquipmentSlot) -> i32 {
+        match self.equipment.get(&slot) {
+            Some(weapon) if self.is_proficient_with(weapon) => self.proficiency_bonus(),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        character::CharacterStats,
+        dice::ScriptedRoller,
+        item::{ItemStats, RaceType},
+    };
+
+    fn character(dexterity: i32, wisdom: i32) -> Character {
+        let stats = CharacterStats::from_scores([10, dexterity, 10, 10, wisdom, 10]);
+        Character::new("Scout".to_string(), RaceType::Human, stats)
+    }
+
+    fn armor(requires: Proficiency) -> Item {
+        let stats = ItemStats {
+            strength_bonus: 0,
+            dexterity_bonus: 0,
+            constitution_bonus: 0,
+            intelligence_bonus: 0,
+            wisdom_bonus: 0,
+            charisma_bonus: 0,
+            armor: 6,
+            damage: None,
+            defenses: Default::default(),
+        };
+        let mut item = Item::new_equipment("Plate".to_string(), EquipmentType::ChestPiece, stats, None, 1);
+        item.requires = Some(requires);
+        item
+    }
+
+    #[test]
+    fn test_skills_and_passives() {
+        let mut scout = character(14, 12);
+        let sentry = character(10, 14);
+        assert_eq!(Skill::Stealth.ability(), Ability::Dexterity);
+        assert_eq!(sentry.passive_perception(), 12);
+
+        scout.train_skill(Skill::Stealth, Training::Expertise);
+        scout.train_skill(Skill::Stealth, Training::Proficient);
+        scout.train_skill(Skill::Perception, Training::Proficient);
+        assert_eq!(scout.skill_training(Skill::Stealth), Some(Training::Expertise));
+        assert_eq!(scout.check_modifier(Check::Skill(Skill::Stealth)), 6);
+        assert_eq!(scout.passive_perception(), 13);
+
+        // 4 + 6 falls short of 12; 6 + 6 meets it
+        assert!(!scout.sneak_past(&sentry, &mut ScriptedRoller::new([4])).success);
+        assert!(scout.sneak_past(&sentry, &mut ScriptedRoller::new([6])).success);
+
+        scout.proficiencies.insert(Proficiency::Tool("Thieves' Tools".to_string()));
+        let lock = scout.tool_check("Thieves' Tools", Ability::Dexterity, 15, &mut ScriptedRoller::new([11]));
+        assert_eq!((lock.roll.value, lock.success), (15, true));
+    }
+
+    #[test]
+    fn test_untrained_armor() {
+        let mut knight = character(10, 10);
+        let plate = armor(Proficiency::HeavyArmor);
+        let id = plate.id.clone();
+        knight.add_to_inventory(plate).unwrap();
+        knight.equip_item(&id).unwrap();
+
+        // Worn without training: disadvantage on attacks and dexterity saves
+        assert!(knight.untrained_armor().is_some());
+        assert_eq!(knight.attack_mode(), RollMode::Disadvantage);
+        let save = knight.saving_throw(Ability::Dexterity, 10, &mut ScriptedRoller::new([18, 3]));
+        assert!(!save.success);
+
+        knight.proficiencies.insert(Proficiency::HeavyArmor);
+        assert!(knight.untrained_armor().is_none());
+        assert_eq!(knight.attack_mode(), RollMode::Normal);
+    }
+}
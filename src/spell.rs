@@ -0,0 +1,509 @@
+//! Spells, spell slots and mana
+
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::{
+    Character,
+    area::AreaShape,
+    character::Ability,
+    check::SaveOutcome,
+    class::{ClassRegistry, MAX_LEVEL},
+    dice::Expr,
+    effect::{EffectDuration, EffectKind, StatusEffect},
+    item::DamageType,
+};
+
+/// Highest spell level; cantrips are level 0
+pub const MAX_SPELL_LEVEL: u32 = 9;
+
+/// Spell slots of each level, 1st to 9th, for each caster level
+pub const SPELL_SLOTS: [[u32; MAX_SPELL_LEVEL as usize]; MAX_LEVEL as usize] = [
+    [2, 0, 0, 0, 0, 0, 0, 0, 0],
+    [3, 0, 0, 0, 0, 0, 0, 0, 0],
+    [4, 2, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 0, 0, 0, 0, 0, 0, 0],
+    [4, 3, 2, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 0, 0, 0, 0, 0, 0],
+    [4, 3, 3, 1, 0, 0, 0, 0, 0],
+    [4, 3, 3, 2, 0, 0, 0, 0, 0],
+    [4, 3, 3, 3, 1, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 0, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 0, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 0, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 0],
+    [4, 3, 3, 3, 2, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 1, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 1, 1, 1],
+    [4, 3, 3, 3, 3, 2, 2, 1, 1],
+];
+
+/// Mana pool size for each caster level
+pub const MANA_POOL: [u32; MAX_LEVEL as usize] = [
+    4, 6, 14, 17, 27, 32, 38, 44, 57, 64, 73, 73, 83, 83, 94, 94, 107, 114, 123, 133,
+];
+
+/// Mana spent on a spell of each level, cantrips first
+pub const MANA_COSTS: [u32; MAX_SPELL_LEVEL as usize + 1] = [0, 2, 3, 5, 6, 7, 9, 10, 11, 13];
+
+/// The spells shipped in `spells/`
+const DEFAULT_SPELLS: [&str; 6] = [
+    include_str!("../spells/fire_bolt.yaml"),
+    include_str!("../spells/magic_missile.yaml"),
+    include_str!("../spells/cure_wounds.yaml"),
+    include_str!("../spells/bless.yaml"),
+    include_str!("../spells/hold_person.yaml"),
+    include_str!("../spells/fireball.yaml"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Spell {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// 0 for cantrips, which cost nothing to cast
+    #[serde(default)]
+    pub level: u32,
+    /// How far from the caster the spell can be aimed, in hexes
+    pub range: i32,
+    /// Shape placed on the aimed hex; without one the spell affects whoever stands there
+    #[serde(default)]
+    pub area: Option<AreaShape>,
+    /// Rolled once and shared by every target
+    #[serde(default)]
+    pub damage: Option<Expr>,
+    #[serde(default = "default_damage_type")]
+    pub damage_type: DamageType,
+    #[serde(default)]
+    pub healing: Option<Expr>,
+    #[serde(default)]
+    pub resolution: SpellResolution,
+    /// Applied to targets the spell lands on, lasting the effect's duration
+    #[serde(default)]
+    pub effect: Option<StatusEffect>,
+    /// The effect ends early if the caster loses concentration
+    #[serde(default)]
+    pub concentration: bool,
+}
+
+/// How a spell decides whether it lands on each target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SpellResolution {
+    /// Always lands, like magic missile
+    #[default]
+    Automatic,
+    /// A spell attack roll against armor class
+    Attack,
+    /// Each target saves against the caster's spell save DC
+    Save {
+        ability: Ability,
+        #[serde(default)]
+        on_success: SaveOutcome,
+    },
+}
+
+fn default_damage_type() -> DamageType {
+    DamageType::Magic
+}
+
+/// What a class spends to cast its spells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceKind {
+    Slots,
+    Mana,
+}
+
+/// Spellcasting granted by a class
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClassSpellcasting {
+    pub ability: Ability,
+    pub resource: ResourceKind,
+    /// Spells learned on taking the class
+    #[serde(default)]
+    pub spells: Vec<String>,
+}
+
+/// A character's magic: what they know, what they have left and what they are holding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spellcasting {
+    pub ability: Ability,
+    /// Levels in spellcasting classes, which decide slots, mana and the highest spell level
+    pub caster_level: i32,
+    pub resource: CastingResource,
+    pub spells: Vec<String>,
+    #[serde(default)]
+    pub concentration: Option<Concentration>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CastingResource {
+    /// Remaining and total slots of each level, 1st to 9th
+    Slots {
+        current: [u32; MAX_SPELL_LEVEL as usize],
+        maximum: [u32; MAX_SPELL_LEVEL as usize],
+    },
+    Mana { current: u32, maximum: u32 },
+}
+
+/// A spell the caster is keeping up, and the effect it placed on its targets
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Concentration {
+    pub spell: String,
+    pub effect: Option<EffectKind>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpellError {
+    NotASpellcaster,
+    UnknownSpell(String),
+    /// The caster is not high enough level for spells of this level
+    TooHighLevel { level: u32, max: u32 },
+    NoSlot { level: u32 },
+    NotEnoughMana { needed: u32, current: u32 },
+    /// Spells cannot be cast while wearing armor the caster is not trained for
+    UntrainedArmor,
+}
+
+impl Spell {
+    /// Spells that deal damage or give their targets a roll to resist are aimed at enemies
+    pub fn is_harmful(&self) -> bool {
+        self.damage.is_some() || self.resolution != SpellResolution::Automatic
+    }
+
+    /// Mana needed to cast the spell from a mana pool
+    pub fn mana_cost(&self) -> u32 {
+        MANA_COSTS[self.level.min(MAX_SPELL_LEVEL) as usize]
+    }
+
+    pub fn duration(&self) -> Option<EffectDuration> {
+        self.effect.as_ref().map(|e| e.duration)
+    }
+}
+
+impl CastingResource {
+    fn empty(kind: ResourceKind) -> Self {
+        match kind {
+            ResourceKind::Slots => CastingResource::Slots {
+                current: [0; MAX_SPELL_LEVEL as usize],
+                maximum: [0; MAX_SPELL_LEVEL as usize],
+            },
+            ResourceKind::Mana => CastingResource::Mana { current: 0, maximum: 0 },
+        }
+    }
+
+    /// Grow to the totals for `caster_level`, gaining whatever was added
+    fn set_caster_level(&mut self, caster_level: i32) {
+        let index = (caster_level.clamp(1, MAX_LEVEL) - 1) as usize;
+        match self {
+            CastingResource::Slots { current, maximum } => {
+                for (level, &total) in SPELL_SLOTS[index].iter().enumerate() {
+                    current[level] += total.saturating_sub(maximum[level]);
+                    maximum[level] = total;
+                }
+            }
+            CastingResource::Mana { current, maximum } => {
+                *current += MANA_POOL[index].saturating_sub(*maximum);
+                *maximum = MANA_POOL[index];
+            }
+        }
+    }
+
+    fn refill(&mut self) {
+        match self {
+            CastingResource::Slots { current, maximum } => *current = *maximum,
+            CastingResource::Mana { current, maximum } => *current = *maximum,
+        }
+    }
+}
+
+impl Spellcasting {
+    /// Highest level of spell the caster level allows, with slots or mana alike
+    pub fn max_spell_level(&self) -> u32 {
+        let index = (self.caster_level.clamp(1, MAX_LEVEL) - 1) as usize;
+        SPELL_SLOTS[index].iter().rposition(|&slots| slots > 0).map_or(0, |i| i as u32 + 1)
+    }
+
+    pub fn knows(&self, spell: &str) -> bool {
+        self.spells.iter().any(|s| s.eq_ignore_ascii_case(spell))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpellBook {
+    spells: HashMap<String, Spell>,
+}
+
+impl SpellBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A spell book with the spells in `spells/` loaded
+    pub fn with_defaults() -> Self {
+        let mut book = Self::new();
+        for yaml in DEFAULT_SPELLS {
+            book.load_spell(yaml).expect("built-in spells are valid");
+        }
+        book
+    }
+
+    /// Add a spell from YAML, replacing any with the same name
+    pub fn load_spell(&mut self, yaml: &str) -> Result<(), serde_yaml::Error> {
+        let spell: Spell = serde_yaml::from_str(yaml)?;
+        self.spells.insert(spell.name.clone(), spell);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Spell> {
+        self.spells.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.spells.keys().map(|name| name.as_str())
+    }
+}
+
+impl Character {
+    /// DC targets save against: 8 + proficiency bonus + spellcasting modifier
+    pub fn spell_save_dc(&self) -> Option<i32> {
+        Some(8 + self.spell_attack_bonus()?)
+    }
+
+    /// Added to spell attack rolls: proficiency bonus + spellcasting modifier
+    pub fn spell_attack_bonus(&self) -> Option<i32> {
+        let ability = self.spellcasting.as_ref()?.ability;
+        Some(self.proficiency_bonus() + self.modifier(ability))
+    }
+
+    pub fn knows_spell(&self, spell: &str) -> bool {
+        self.spellcasting.as_ref().is_some_and(|s| s.knows(spell))
+    }
+
+    pub fn learn_spell(&mut self, spell: &str) -> Result<(), SpellError> {
+        let spellcasting = self.spellcasting.as_mut().ok_or(SpellError::NotASpellcaster)?;
+        if !spellcasting.knows(spell) {
+            spellcasting.spells.push(spell.to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the character knows `spell` and has the slot or mana to cast it now
+    pub fn can_cast(&self, spell: &Spell) -> Result<(), SpellError> {
+        let spellcasting = self.spellcasting.as_ref().ok_or(SpellError::NotASpellcaster)?;
+        if !spellcasting.knows(&spell.name) {
+            return Err(SpellError::UnknownSpell(spell.name.clone()));
+        }
+        let max = spellcasting.max_spell_level();
+        if spell.level > max {
+            return Err(SpellError::TooHighLevel { level: spell.level, max });
+        }
+        if self.untrained_armor().is_some() {
+            return Err(SpellError::UntrainedArmor);
+        }
+        if spell.level == 0 {
+            return Ok(());
+        }
+        match &spellcasting.resource {
+            CastingResource::Slots { current, .. } => {
+                if current[spell.level as usize - 1..].iter().all(|&slots| slots == 0) {
+                    return Err(SpellError::NoSlot { level: spell.level });
+                }
+            }
+            CastingResource::Mana { current, .. } => {
+                if *current < spell.mana_cost() {
+                    return Err(SpellError::NotEnoughMana { needed: spell.mana_cost(), current: *current });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pay for `spell`, with the lowest slot that fits or from the mana pool
+    pub fn spend_spell(&mut self, spell: &Spell) -> Result<(), SpellError> {
+        self.can_cast(spell)?;
+        if spell.level == 0 {
+            return Ok(());
+        }
+        match &mut self.spellcasting.as_mut().ok_or(SpellError::NotASpellcaster)?.resource {
+            CastingResource::Slots { current, .. } => {
+                let slot = current[spell.level as usize - 1..]
+                    .iter_mut()
+                    .find(|slots| **slots > 0)
+                    .ok_or(SpellError::NoSlot { level: spell.level })?;
+                *slot -= 1;
+            }
+            CastingResource::Mana { current, .. } => *current -= spell.mana_cost(),
+        }
+        Ok(())
+    }
+
+    /// Restore up to `amount` mana, returning how much came back
+    ///
+    /// Casters with slots instead regain expended slots, lowest first, whose
+    /// levels add up to no more than `amount`.
+    pub fn restore_mana(&mut self, amount: u32) -> u32 {
+        let Some(spellcasting) = self.spellcasting.as_mut() else {
+            return 0;
+        };
+        match &mut spellcasting.resource {
+            CastingResource::Slots { current, maximum } => {
+                let mut restored = 0;
+                for level in 1..=MAX_SPELL_LEVEL {
+                    let index = level as usize - 1;
+                    while current[index] < maximum[index] && restored + level <= amount {
+                        current[index] += 1;
+                        restored += level;
+                    }
+                }
+                restored
+            }
+            CastingResource::Mana { current, maximum } => {
+                let restored = amount.min(*maximum - *current);
+                *current += restored;
+                restored
+            }
+        }
+    }
+
+    /// An hour's rest: a mana pool refills to at least half; slots wait for a long rest
+    pub fn short_rest(&mut self) {
+        if let Some(CastingResource::Mana { current, maximum }) =
+            self.spellcasting.as_mut().map(|s| &mut s.resource)
+        {
+            *current = (*current).max(*maximum / 2);
+        }
+    }
+
+    /// A night's rest: full health, slots and mana, and any held spell let go
+    pub fn long_rest(&mut self) {
+        self.health.current = self.health.maximum;
+        if let Some(spellcasting) = self.spellcasting.as_mut() {
+            spellcasting.resource.refill();
+            spellcasting.concentration = None;
+        }
+    }
+
+    /// Bring slots, mana and known spells up to date with the character's classes
+    pub(crate) fn advance_spellcasting(&mut self, registry: &ClassRegistry) {
+        let casting: Vec<(i32, &ClassSpellcasting)> = self
+            .classes
+            .iter()
+            .filter_map(|c| Some((c.level, registry.get(&c.class)?.spellcasting.as_ref()?)))
+            .collect();
+        let Some((_, first)) = casting.first() else {
+            return;
+        };
+        let caster_level: i32 = casting.iter().map(|(level, _)| level).sum();
+
+        let spellcasting = self.spellcasting.get_or_insert_with(|| Spellcasting {
+            ability: first.ability,
+            caster_level,
+            resource: CastingResource::empty(first.resource),
+            spells: Vec::new(),
+            concentration: None,
+        });
+        spellcasting.caster_level = caster_level;
+        spellcasting.resource.set_caster_level(caster_level);
+        for spell in casting.iter().flat_map(|(_, class)| &class.spells) {
+            if !spellcasting.knows(spell) {
+                spellcasting.spells.push(spell.clone());
+            }
+        }
+    }
+}
+
+impl fmt::Display for SpellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpellError::NotASpellcaster => write!(f, "character cannot cast spells"),
+            SpellError::UnknownSpell(name) => write!(f, "character does not know {}", name),
+            SpellError::TooHighLevel { level, max } => {
+                write!(f, "level {} spells are beyond the caster's level {} maximum", level, max)
+            }
+            SpellError::NoSlot { level } => write!(f, "no spell slot of level {} or higher left", level),
+            SpellError::NotEnoughMana { needed, current } => {
+                write!(f, "spell costs {} mana but only {} remains", needed, current)
+            }
+            SpellError::UntrainedArmor => write!(f, "cannot cast spells in armor without training"),
+        }
+    }
+}
+
+impl std::error::Error for SpellError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{character::CharacterStats, dice::ScriptedRoller, item::RaceType};
+
+    fn wizard(registry: &ClassRegistry) -> Character {
+        let stats = CharacterStats::from_scores([8, 14, 12, 16, 12, 10]);
+        let mut wizard = Character::new("Mage".to_string(), RaceType::Elf, stats);
+        wizard.start_class("Wizard", registry).unwrap();
+        wizard
+    }
+
+    #[test]
+    fn test_spell_slots_and_rests() {
+        let registry = ClassRegistry::with_defaults();
+        let book = SpellBook::with_defaults();
+        let mut mage = wizard(&registry);
+        assert_eq!((mage.spell_save_dc(), mage.spell_attack_bonus()), (Some(13), Some(5)));
+
+        let missile = book.get("Magic Missile").unwrap();
+        let fireball = book.get("Fireball").unwrap();
+        assert_eq!(mage.can_cast(fireball), Err(SpellError::TooHighLevel { level: 3, max: 1 }));
+        mage.spend_spell(missile).unwrap();
+        mage.spend_spell(missile).unwrap();
+        assert_eq!(mage.can_cast(missile), Err(SpellError::NoSlot { level: 1 }));
+        // Cantrips cost nothing
+        assert!(mage.can_cast(book.get("Fire Bolt").unwrap()).is_ok());
+
+        // A mana potion's worth of recovery brings back both 1st level slots
+        assert_eq!(mage.restore_mana(3), 2);
+        mage.spend_spell(missile).unwrap();
+
+        mage.gain_experience(6_500);
+        for _ in 0..4 {
+            mage.level_up("Wizard", &registry, &mut ScriptedRoller::new([4])).unwrap();
+        }
+        assert!(mage.can_cast(fireball).is_ok());
+        mage.spend_spell(fireball).unwrap();
+        mage.long_rest();
+        let Some(Spellcasting { resource: CastingResource::Slots { current, .. }, .. }) = &mage.spellcasting else {
+            panic!("wizards cast with slots");
+        };
+        assert_eq!(current[..3], [4, 3, 2]);
+    }
+
+    #[test]
+    fn test_mana_pool() {
+        let mut registry = ClassRegistry::with_defaults();
+        registry
+            .load_class(
+                r#"
+name: "Sorcerer"
+hit_die: 6
+primary_ability: Charisma
+spellcasting: { ability: Charisma, resource: Mana, spells: ["Magic Missile"] }
+"#,
+            )
+            .unwrap();
+        let book = SpellBook::with_defaults();
+        let missile = book.get("Magic Missile").unwrap();
+
+        let stats = CharacterStats::from_scores([10; 6]);
+        let mut sorcerer = Character::new("Sorcerer".to_string(), RaceType::Human, stats);
+        sorcerer.start_class("Sorcerer", &registry).unwrap();
+        sorcerer.spend_spell(missile).unwrap();
+        sorcerer.spend_spell(missile).unwrap();
+        assert_eq!(sorcerer.can_cast(missile), Err(SpellError::NotEnoughMana { needed: 2, current: 0 }));
+
+        sorcerer.short_rest();
+        assert!(sorcerer.can_cast(missile).is_ok());
+        assert_eq!(sorcerer.restore_mana(10), 2);
+    }
+}